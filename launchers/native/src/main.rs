@@ -1,7 +1,16 @@
 use std::io::Cursor;
 
 use bevy::{prelude::*, window::WindowId, winit::WinitWindows};
-use image;
+use bevy_bird::bridge;
+use bevy_bird::config::GameConfigPath;
+use bevy_bird::course::{self, Course};
+use bevy_bird::editor;
+use bevy_bird::ghost;
+use bevy_bird::leaderboard;
+use bevy_bird::online::{self, TcpRelay};
+use bevy_bird::replay::{self, Replay};
+use bevy_bird::seed::GameSeed;
+use bevy_bird::storage::{FileStorage, GameStorage};
 use winit::window::Icon;
 
 fn set_window_icon(windows: NonSend<WinitWindows>) {
@@ -24,10 +33,107 @@ fn set_window_icon(windows: NonSend<WinitWindows>) {
     primary.set_window_icon(Some(icon));
 }
 
+/// The room joined on the relay unless `--room` picks another.
+const DEFAULT_ROOM: &str = "lobby";
+
+/// Applies the command line arguments to the app.
+fn parse_args(app: &mut App) {
+    let mut relay_addr = None;
+    let mut room = DEFAULT_ROOM.to_string();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => match args.next() {
+                Some(path) => {
+                    app.insert_resource(GameConfigPath(path.into()));
+                }
+                None => error!("--config needs a path to a config file"),
+            },
+            "--seed" => match args.next().map(|seed| seed.parse()) {
+                Some(Ok(seed)) => {
+                    app.insert_resource(GameSeed::fixed(seed));
+                }
+                _ => error!("--seed needs a whole number"),
+            },
+            "--replay" => match args.next().map(Replay::load) {
+                Some(Ok(loaded)) => replay::play_replay(app, loaded),
+                Some(Err(err)) => error!("Unable to load replay: {}", err),
+                None => error!("--replay needs a path to a replay file"),
+            },
+            "--course" => match args.next().map(Course::load) {
+                Some(Ok(loaded)) => course::play_course(app, loaded),
+                Some(Err(err)) => error!("Unable to load course: {}", err),
+                None => error!("--course needs a path to a course file"),
+            },
+            "--edit" => match args.next() {
+                Some(path) => {
+                    if let Err(err) = editor::edit_course(app, &path) {
+                        error!("Unable to open course {}: {}", path, err);
+                    }
+                }
+                None => error!("--edit needs a path to a course file"),
+            },
+            "--ghost" => match args.next().map(Replay::load) {
+                Some(Ok(loaded)) => ghost::race_replay(app, loaded),
+                Some(Err(err)) => error!("Unable to load ghost: {}", err),
+                None => error!("--ghost needs a path to a replay file"),
+            },
+            "--bridge" => match args.next() {
+                Some(addr) => {
+                    if let Err(err) = bridge::serve(app, addr.as_str()) {
+                        error!(
+                            "Unable to listen for agents on {}: {}",
+                            addr, err
+                        );
+                    }
+                }
+                None => {
+                    error!("--bridge needs an address such as 127.0.0.1:7777")
+                }
+            },
+            "--leaderboard" => match args.next() {
+                Some(url) => {
+                    if let Err(err) = leaderboard::use_server(app, &url) {
+                        error!("Unable to use the leaderboard: {}", err);
+                    }
+                }
+                None => error!(
+                    "--leaderboard needs a URL such as http://127.0.0.1:8080"
+                ),
+            },
+            "--online" => match args.next() {
+                Some(addr) => relay_addr = Some(addr),
+                None => error!("--online needs the relay's address"),
+            },
+            "--room" => match args.next() {
+                Some(name) => room = name,
+                None => error!("--room needs a name"),
+            },
+            _ => warn!("Ignoring unknown argument: {}", arg),
+        }
+    }
+
+    if let Some(addr) = relay_addr {
+        match TcpRelay::connect(addr.as_str()) {
+            Ok(relay) => online::connect(app, relay, &room),
+            Err(err) => {
+                error!("Unable to reach the relay at {}: {}", addr, err)
+            }
+        }
+    }
+}
+
 fn main() {
     let mut app = bevy_bird::app();
+    parse_args(&mut app);
 
     info!("Starting launcher: Native");
     app.add_startup_system(set_window_icon);
+    match FileStorage::in_data_dir() {
+        Some(storage) => {
+            app.insert_resource(GameStorage::new(storage));
+        }
+        None => warn!("No data directory found, scores will not be saved"),
+    }
     app.run();
 }
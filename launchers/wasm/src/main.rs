@@ -1,18 +1,147 @@
+use std::cell::RefCell;
+use std::io;
+use std::sync::{Arc, Mutex};
+
 use bevy::prelude::*;
+use bevy::window::{WindowFocused, WindowId};
+use stylist::global_style;
 use stylist::yew::styled_component;
-use stylist::{css, global_style};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{MessageEvent, WebSocket};
 use yew::prelude::*;
 
+use bevy_bird::online::{self, RelayConnection};
+use bevy_bird::seed::GameSeed;
+use bevy_bird::storage::{GameStorage, Storage};
 use bevy_bird::LAUNCHER_TITLE;
 
 fn set_window_title(title: &str) {
     web_sys::window()
-        .map(|w| w.document())
-        .flatten()
+        .and_then(|w| w.document())
         .expect("Unable to get DOM")
         .set_title(title);
 }
 
+/// The browser only reports focus changes to the canvas once it has been
+/// focused itself, so poll the page and forward changes to the game instead.
+fn forward_page_focus(
+    mut page_focused: Local<bool>,
+    mut focus_evw: EventWriter<WindowFocused>,
+) {
+    let focused = web_sys::window()
+        .and_then(|w| w.document())
+        .and_then(|d| d.has_focus().ok())
+        .unwrap_or(true);
+
+    if focused != *page_focused {
+        *page_focused = focused;
+        focus_evw.send(WindowFocused {
+            id: WindowId::primary(),
+            focused,
+        });
+    }
+}
+
+/// Stores every key in the browser's `window.localStorage`.
+struct LocalStorage;
+
+impl LocalStorage {
+    fn storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok().flatten()
+    }
+}
+
+impl Storage for LocalStorage {
+    fn load(&self, key: &str) -> Option<String> {
+        LocalStorage::storage()?.get_item(key).ok().flatten()
+    }
+
+    fn save(&self, key: &str, value: &str) -> io::Result<()> {
+        LocalStorage::storage()
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "No localStorage")
+            })?
+            .set_item(key, value)
+            .map_err(|err| io::Error::other(format!("{:?}", err)))
+    }
+}
+
+thread_local! {
+    /// Sockets only live on the page's thread, which the game runs on too.
+    static RELAY_SOCKET: RefCell<Option<WebSocket>> = const { RefCell::new(None) };
+}
+
+/// Reaches the relay over a WebSocket, the only socket a page can open.
+struct WebSocketRelay {
+    received: Arc<Mutex<Vec<String>>>,
+    /// Messages sent before the socket finished opening.
+    unsent: Arc<Mutex<Vec<String>>>,
+}
+
+impl WebSocketRelay {
+    fn connect(url: &str) -> Result<Self, JsValue> {
+        let socket = WebSocket::new(url)?;
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let unsent = Arc::new(Mutex::new(Vec::<String>::new()));
+
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new({
+            let received = received.clone();
+            move |event: MessageEvent| {
+                if let Some(text) = event.data().as_string() {
+                    received.lock().unwrap().push(text);
+                }
+            }
+        });
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        on_message.forget();
+
+        let on_open = Closure::<dyn FnMut()>::new({
+            let unsent = unsent.clone();
+            move || {
+                RELAY_SOCKET.with(|socket| {
+                    if let Some(socket) = socket.borrow().as_ref() {
+                        for message in unsent.lock().unwrap().drain(..) {
+                            let _ = socket.send_with_str(&message);
+                        }
+                    }
+                })
+            }
+        });
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        on_open.forget();
+
+        RELAY_SOCKET.with(|cell| *cell.borrow_mut() = Some(socket));
+        Ok(WebSocketRelay { received, unsent })
+    }
+}
+
+impl RelayConnection for WebSocketRelay {
+    fn send(&self, message: &str) -> io::Result<()> {
+        RELAY_SOCKET.with(|socket| match socket.borrow().as_ref() {
+            Some(socket) if socket.ready_state() == WebSocket::CONNECTING => {
+                self.unsent.lock().unwrap().push(message.to_string());
+                Ok(())
+            }
+            Some(socket) => socket
+                .send_with_str(message)
+                .map_err(|err| io::Error::other(format!("{:?}", err))),
+            None => Err(io::ErrorKind::NotConnected.into()),
+        })
+    }
+
+    fn receive(&self) -> Vec<String> {
+        std::mem::take(&mut *self.received.lock().unwrap())
+    }
+}
+
+/// Reads a parameter from the page URL, e.g. `?seed=1234`.
+fn url_param(name: &str) -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    let params = web_sys::UrlSearchParams::new_with_str(&search).ok()?;
+    params.get(name)
+}
+
 fn set_global_css() {
     global_style! {
         r#"
@@ -57,5 +186,20 @@ fn main() {
     // Start the Bevy App
     let mut app = bevy_bird::app();
     info!("Starting launcher: WASM");
+    app.add_system(forward_page_focus);
+    app.insert_resource(GameStorage::new(LocalStorage));
+    if let Some(seed) = url_param("seed").and_then(|seed| seed.parse().ok()) {
+        app.insert_resource(GameSeed::fixed(seed));
+    }
+    // Race online with `?relay=ws://127.0.0.1:7878&room=lobby`
+    if let Some(url) = url_param("relay") {
+        let room = url_param("room").unwrap_or_else(|| "lobby".to_string());
+        match WebSocketRelay::connect(&url) {
+            Ok(relay) => online::connect(&mut app, relay, &room),
+            Err(err) => {
+                error!("Unable to reach the relay at {}: {:?}", url, err)
+            }
+        }
+    }
     app.run();
 }
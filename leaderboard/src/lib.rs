@@ -0,0 +1,348 @@
+//! Keeps the game's global scores in SQLite, and checks every run before
+//! taking it.
+//!
+//! The game posts a finished run to `/scores`, as described in
+//! `bevy_bird::leaderboard`. The run's replay is played on a headless copy of
+//! the game with the server's config, and the score is only stored if the
+//! replay ends on it. The answer is where the run ranks, or `422` with the
+//! reason it was turned down. `GET /scores` lists the best scores.
+//!
+//! Replays are played back a few at a time, and runs posted while too many
+//! are waiting are answered with `503`.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::net::TcpListener;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use bevy_bird::config::GameConfig;
+use bevy_bird::leaderboard::{Ranking, Submission, TOP_SCORES};
+use bevy_bird::replay::{self, TICK_SECS};
+use rusqlite::{params, Connection};
+use socket2::SockRef;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// An hour of play. Longer replays are turned down rather than simulated.
+const MAX_RUN_TICKS: u64 = (60.0 * 60.0 / TICK_SECS) as u64;
+
+/// Requests with a bigger body than this are turned down unread.
+const MAX_BODY_BYTES: usize = 1 << 20;
+
+/// How many requests are read and answered at once.
+const WORKERS: usize = 4;
+
+/// How many replays are played back at once. Each keeps a core busy for as
+/// long as the run lasted, at worst.
+const CHECKERS: usize = 2;
+
+/// How many runs can wait for a checker before more are turned away.
+const QUEUED_RUNS: usize = 16;
+
+/// How long a client can leave the server waiting on its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the server waits on a client to take its answer.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Why a run wasn't taken.
+#[derive(Debug)]
+pub enum Error {
+    /// The run doesn't hold up.
+    Rejected(String),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Rejected(reason) => write!(f, "run rejected: {}", reason),
+            Error::Database(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Database(err)
+    }
+}
+
+/// The stored scores and the config runs are checked with.
+pub struct Leaderboard {
+    database: Mutex<Connection>,
+    config: GameConfig,
+}
+
+impl Leaderboard {
+    /// Opens the scores kept at `path`, which is created if needed.
+    pub fn open(
+        path: &str,
+        config: GameConfig,
+    ) -> Result<Self, rusqlite::Error> {
+        let database = Connection::open(path)?;
+        database.execute(
+            "CREATE TABLE IF NOT EXISTS scores (
+                id INTEGER PRIMARY KEY,
+                score INTEGER NOT NULL,
+                seed INTEGER NOT NULL,
+                replay TEXT NOT NULL,
+                submitted_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+        database.execute(
+            "CREATE INDEX IF NOT EXISTS scores_by_score ON scores (score)",
+            [],
+        )?;
+
+        Ok(Leaderboard {
+            database: Mutex::new(database),
+            config,
+        })
+    }
+
+    /// Plays the run back and stores its score if the replay ends on it.
+    pub fn submit(&self, submission: &Submission) -> Result<Ranking, Error> {
+        let replay = &submission.replay;
+        if replay.seed != submission.seed {
+            return Err(Error::Rejected(
+                "the replay is of another course".to_string(),
+            ));
+        }
+        if replay.config_hash != replay::config_hash(&self.config) {
+            return Err(Error::Rejected(
+                "the replay was played with another game config".to_string(),
+            ));
+        }
+        if replay
+            .flaps
+            .last()
+            .is_some_and(|&last| last > MAX_RUN_TICKS)
+        {
+            return Err(Error::Rejected("the replay is too long".to_string()));
+        }
+        let score = i64::try_from(submission.score).map_err(|_| {
+            Error::Rejected("the score is out of range".to_string())
+        })?;
+
+        match replay::simulate(replay, self.config.clone()) {
+            Some(simulated) if simulated == submission.score => {}
+            Some(simulated) => {
+                return Err(Error::Rejected(format!(
+                    "the replay scores {}, not {}",
+                    simulated, submission.score
+                )))
+            }
+            None => {
+                return Err(Error::Rejected(
+                    "the replay never ends".to_string(),
+                ))
+            }
+        }
+
+        let replay = serde_json::to_string(replay)
+            .map_err(|err| Error::Rejected(err.to_string()))?;
+        let database = self.database.lock().unwrap();
+        database.execute(
+            "INSERT INTO scores (score, seed, replay) VALUES (?, ?, ?)",
+            // The seed's bits are stored as is, SQLite has no unsigned
+            // integers
+            params![score, submission.seed as i64, replay],
+        )?;
+
+        let better: u64 = database.query_row(
+            "SELECT COUNT(*) FROM scores WHERE score > ?",
+            [score],
+            |row| row.get(0),
+        )?;
+        let total: u64 =
+            database.query_row("SELECT COUNT(*) FROM scores", [], |row| {
+                row.get(0)
+            })?;
+        Ok(Ranking {
+            rank: better + 1,
+            total,
+            top: top_scores(&database)?,
+        })
+    }
+
+    /// The best scores, highest first.
+    pub fn top(&self) -> Result<Vec<u128>, rusqlite::Error> {
+        top_scores(&self.database.lock().unwrap())
+    }
+}
+
+fn top_scores(database: &Connection) -> Result<Vec<u128>, rusqlite::Error> {
+    let mut statement = database
+        .prepare("SELECT score FROM scores ORDER BY score DESC LIMIT ?")?;
+    let scores = statement
+        .query_map([TOP_SCORES], |row| row.get::<_, u64>(0))?
+        .map(|score| score.map(u128::from))
+        .collect();
+    scores
+}
+
+/// A posted run, waiting to be played back.
+struct Run {
+    request: Request,
+    submission: Submission,
+}
+
+/// Answers every request made to `listener`, forever.
+///
+/// Requests are answered by a fixed number of workers. Posted runs are
+/// queued for the checkers, which play them back and answer them, and are
+/// turned away with `503` while the queue is full.
+pub fn serve(
+    listener: TcpListener,
+    leaderboard: Arc<Leaderboard>,
+) -> io::Result<()> {
+    // Taken on by every connection accepted from here on, so a client that
+    // stops sending can't hold on to a worker
+    let socket = SockRef::from(&listener);
+    socket.set_read_timeout(Some(READ_TIMEOUT))?;
+    socket.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let server = Arc::new(
+        Server::from_listener(listener, None).map_err(io::Error::other)?,
+    );
+
+    let (runs, queued) = mpsc::sync_channel(QUEUED_RUNS);
+    let queued = Arc::new(Mutex::new(queued));
+    for _ in 0..CHECKERS {
+        let queued = queued.clone();
+        let leaderboard = leaderboard.clone();
+        thread::spawn(move || check_runs(&queued, &leaderboard));
+    }
+
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|_| {
+            let server = server.clone();
+            let leaderboard = leaderboard.clone();
+            let runs = runs.clone();
+            thread::spawn(move || loop {
+                match server.recv() {
+                    Ok(request) => answer(request, &leaderboard, &runs),
+                    Err(err) => eprintln!("Unable to take request: {}", err),
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker
+            .join()
+            .map_err(|_| io::Error::other("a worker panicked"))?;
+    }
+    Ok(())
+}
+
+/// Answers one request, or queues it for the checkers if it posts a run.
+fn answer(
+    mut request: Request,
+    leaderboard: &Leaderboard,
+    runs: &SyncSender<Run>,
+) {
+    let (status, body) = match (request.method(), request.url()) {
+        (Method::Post, "/scores") => match read_submission(&mut request) {
+            Ok(submission) => {
+                let run = Run {
+                    request,
+                    submission,
+                };
+                let (run, status, reason) = match runs.try_send(run) {
+                    Ok(()) => return,
+                    Err(TrySendError::Full(run)) => {
+                        (run, 503, "too many runs are waiting to be checked")
+                    }
+                    Err(TrySendError::Disconnected(run)) => {
+                        (run, 500, "runs are no longer checked")
+                    }
+                };
+                return respond(run.request, status, reason.to_string());
+            }
+            Err(answer) => answer,
+        },
+        (Method::Get, "/scores") => match leaderboard.top() {
+            Ok(top) => (200, format!("{{\"top\":{:?}}}", top)),
+            Err(err) => (500, err.to_string()),
+        },
+        _ => (404, "not found".to_string()),
+    };
+    respond(request, status, body);
+}
+
+/// The run posted in `request`, or what to answer if there is none.
+fn read_submission(request: &mut Request) -> Result<Submission, (u16, String)> {
+    let too_big = || (413, "the request is too big".to_string());
+    if request
+        .body_length()
+        .is_some_and(|length| length > MAX_BODY_BYTES)
+    {
+        return Err(too_big());
+    }
+
+    // One past the limit, to tell a body that long from a longer one
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_BYTES as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|err| (400, err.to_string()))?;
+    if body.len() > MAX_BODY_BYTES {
+        return Err(too_big());
+    }
+    serde_json::from_slice(&body).map_err(|err| (400, err.to_string()))
+}
+
+/// Plays back queued runs and answers them, for as long as runs come in.
+fn check_runs(queued: &Mutex<Receiver<Run>>, leaderboard: &Leaderboard) {
+    loop {
+        let run = match queued.lock().unwrap().recv() {
+            Ok(run) => run,
+            Err(_) => return,
+        };
+        // A replay that trips up the game only costs its own request
+        let (status, body) = panic::catch_unwind(AssertUnwindSafe(|| {
+            check(leaderboard, &run.submission)
+        }))
+        .unwrap_or_else(|_| (500, "the replay crashed the game".to_string()));
+        respond(run.request, status, body);
+    }
+}
+
+fn check(leaderboard: &Leaderboard, submission: &Submission) -> (u16, String) {
+    match leaderboard.submit(submission) {
+        Ok(ranking) => match serde_json::to_string(&ranking) {
+            Ok(ranking) => (200, ranking),
+            Err(err) => (500, err.to_string()),
+        },
+        Err(Error::Rejected(reason)) => {
+            println!("Rejected a score of {}: {}", submission.score, reason);
+            (422, reason)
+        }
+        Err(err) => (500, err.to_string()),
+    }
+}
+
+fn respond(request: Request, status: u16, body: String) {
+    let content_type = if status == 200 {
+        "application/json"
+    } else {
+        "text/plain"
+    };
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(
+            Header::from_bytes("Content-Type", content_type)
+                .expect("a valid header"),
+        );
+    let peer = request.remote_addr().copied();
+    if let Err(err) = request.respond(response) {
+        eprintln!("Unable to answer {:?}: {}", peer, err);
+    }
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::net::TcpListener;
+use std::process;
+use std::sync::Arc;
+
+use bevy_bird::config::GameConfig;
+use leaderboard::Leaderboard;
+
+/// Where the server listens unless `--addr` says otherwise.
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+/// Where the scores are kept unless `--database` says otherwise.
+const DEFAULT_DATABASE: &str = "leaderboard.sqlite3";
+
+fn main() {
+    let mut addr = DEFAULT_ADDR.to_string();
+    let mut database = DEFAULT_DATABASE.to_string();
+    // Runs are checked with the game's own defaults unless the game is
+    // shipped with a tuned config
+    let mut config = GameConfig::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--addr", Some(value)) => addr = value,
+            ("--database", Some(value)) => database = value,
+            ("--config", Some(path)) => {
+                config = match fs::read(&path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|bytes| {
+                        GameConfig::from_ron(&bytes)
+                            .map_err(|err| err.to_string())
+                    }) {
+                    Ok(config) => config,
+                    Err(err) => {
+                        fail(&format!("Unable to load {}: {}", path, err))
+                    }
+                }
+            }
+            _ => fail(&format!(
+                "Usage: leaderboard [--addr {}] [--database {}] \
+                 [--config game.config.ron]",
+                DEFAULT_ADDR, DEFAULT_DATABASE
+            )),
+        }
+    }
+
+    let leaderboard = match Leaderboard::open(&database, config) {
+        Ok(leaderboard) => leaderboard,
+        Err(err) => fail(&format!("Unable to open {}: {}", database, err)),
+    };
+    let listener = match TcpListener::bind(&addr) {
+        Ok(listener) => listener,
+        Err(err) => fail(&format!("Unable to listen on {}: {}", addr, err)),
+    };
+
+    println!(
+        "Keeping scores in {}, listening on http://{}",
+        database, addr
+    );
+    if let Err(err) = leaderboard::serve(listener, Arc::new(leaderboard)) {
+        fail(&format!("Unable to serve on {}: {}", addr, err));
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
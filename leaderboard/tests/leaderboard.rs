@@ -0,0 +1,117 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy_bird::autopilot::Autopilot;
+use bevy_bird::config::GameConfig;
+use bevy_bird::controls::ActionButton;
+use bevy_bird::leaderboard::{GlobalRank, Ranking, Submission};
+use bevy_bird::replay::Replay;
+use bevy_bird::seed::GameSeed;
+use bevy_bird::storage::GameStorage;
+use bevy_bird::{headless_app, ActionKey, AppState, Score};
+use leaderboard::Leaderboard;
+
+const SEED: u64 = 7;
+const SCORE: u128 = 3;
+
+fn start_server() -> String {
+    let leaderboard =
+        Leaderboard::open(":memory:", GameConfig::default()).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || leaderboard::serve(listener, Arc::new(leaderboard)));
+    url
+}
+
+fn state(app: &App) -> AppState {
+    app.world.resource::<State<AppState>>().current().clone()
+}
+
+#[test]
+fn runs_are_ranked_only_if_their_replay_scores() {
+    let url = start_server();
+    let mut app = headless_app();
+    app.insert_resource(GameSeed::fixed(SEED))
+        .insert_resource(ActionKey(ActionButton::Key(KeyCode::Space)))
+        .insert_resource(Autopilot);
+    bevy_bird::leaderboard::use_server(&mut app, &url).unwrap();
+    app.update();
+    app.world
+        .resource_mut::<State<AppState>>()
+        .set(AppState::InGame)
+        .unwrap();
+
+    while app.world.resource::<Score>().0 < SCORE {
+        app.update();
+    }
+    // Left alone, the bird drops to the ground
+    app.world.remove_resource::<Autopilot>();
+    while state(&app) == AppState::InGame {
+        app.update();
+    }
+    assert_eq!(state(&app), AppState::GameOver);
+
+    let started = Instant::now();
+    let ranking = loop {
+        app.update();
+        if let Some(rank) = app.world.get_resource::<GlobalRank>() {
+            break rank.0.clone();
+        }
+        assert!(
+            started.elapsed() < Duration::from_secs(60),
+            "the server never answered"
+        );
+        thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(
+        ranking,
+        Ranking {
+            rank: 1,
+            total: 1,
+            top: vec![SCORE],
+        }
+    );
+
+    let saved = app.world.resource::<GameStorage>().load("last_replay");
+    let replay: Replay = ron::from_str(&saved.unwrap()).unwrap();
+    let inflated = Submission {
+        score: SCORE + 1,
+        seed: SEED,
+        replay,
+    };
+    let err = bevy_bird::leaderboard::submit(&url, &inflated).unwrap_err();
+    assert!(err.to_string().contains("422"), "{}", err);
+}
+
+#[test]
+fn oversized_and_stalled_requests_are_turned_away() {
+    let url = start_server();
+    let addr = url.strip_prefix("http://").unwrap();
+
+    let mut oversized = TcpStream::connect(addr).unwrap();
+    write!(
+        oversized,
+        "POST /scores HTTP/1.1\r\nHost: {}\r\n\
+         Content-Length: 100000000\r\n\r\n",
+        addr
+    )
+    .unwrap();
+    let mut response = [0; 12];
+    oversized.read_exact(&mut response).unwrap();
+    assert_eq!(&response, b"HTTP/1.1 413");
+
+    // Never finishes its request, so the server gives up on it
+    let mut stalled = TcpStream::connect(addr).unwrap();
+    stalled
+        .set_read_timeout(Some(Duration::from_secs(60)))
+        .unwrap();
+    write!(stalled, "POST /scores HTTP/1.1\r\n").unwrap();
+    let mut response = Vec::new();
+    stalled
+        .read_to_end(&mut response)
+        .expect("the server kept waiting");
+}
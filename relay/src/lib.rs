@@ -0,0 +1,168 @@
+//! Pairs up players for online versus runs and passes their messages
+//! between them.
+//!
+//! Clients connect over plain TCP, one JSON message per line, or over a
+//! WebSocket on the same port, one JSON message per text frame. The first
+//! message names the room to play in:
+//!
+//! ```text
+//! {"join":{"room":"lobby"}}
+//! ```
+//!
+//! Once a second client joins the room, the relay picks a course and tells
+//! both, numbering the players:
+//!
+//! ```text
+//! {"start":{"seed":1234,"player":0}}
+//! ```
+//!
+//! From then on every message a client sends is passed on as is to the other,
+//! until one of them disconnects and the other is sent `"left"`. A client
+//! sending a message over 4 KiB is disconnected.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use websocket::{Reader, Writer};
+
+mod websocket;
+
+/// The messages the relay reads or writes itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Message {
+    Join { room: String },
+    Start { seed: u64, player: usize },
+    Left,
+}
+
+struct Client {
+    writer: Mutex<Writer>,
+    opponent: Mutex<Option<Arc<Client>>>,
+}
+
+impl Client {
+    fn send(&self, message: &str) -> io::Result<()> {
+        self.writer.lock().unwrap().send(message)
+    }
+
+    fn send_message(&self, message: &Message) -> io::Result<()> {
+        self.send(&serde_json::to_string(message)?)
+    }
+}
+
+/// The client waiting for an opponent in each room.
+type Rooms = Arc<Mutex<HashMap<String, Arc<Client>>>>;
+
+/// Relays on a free local port in the background, for playing against
+/// yourself or testing. Returns where it listens.
+pub fn spawn_local() -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    thread::spawn(move || serve(listener));
+    Ok(addr)
+}
+
+/// Relays for every client that connects to `listener`, forever.
+pub fn serve(listener: TcpListener) {
+    let rooms = Rooms::default();
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let rooms = rooms.clone();
+                thread::spawn(move || {
+                    let peer = stream.peer_addr();
+                    if let Err(err) = handle(stream, &rooms) {
+                        eprintln!("Dropped client {:?}: {}", peer, err);
+                    }
+                });
+            }
+            Err(err) => eprintln!("Unable to accept client: {}", err),
+        }
+    }
+}
+
+fn handle(stream: TcpStream, rooms: &Rooms) -> io::Result<()> {
+    let (mut reader, writer) = websocket::accept(stream)?;
+    let client = Arc::new(Client {
+        writer: Mutex::new(writer),
+        opponent: Mutex::new(None),
+    });
+
+    let room = match reader.read()? {
+        Some(line) => match serde_json::from_str(&line)? {
+            Message::Join { room } => room,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("expected to join a room first, got {:?}", other),
+                ))
+            }
+        },
+        None => return Ok(()),
+    };
+    join(rooms, &room, &client);
+
+    let relayed = relay(&mut reader, &client);
+    leave(rooms, &room, &client);
+    relayed
+}
+
+/// Pairs `client` with whoever is waiting in `room`, or leaves it waiting
+/// there.
+fn join(rooms: &Rooms, room: &str, client: &Arc<Client>) {
+    let mut rooms = rooms.lock().unwrap();
+    let waiting = match rooms.remove(room) {
+        Some(waiting) => waiting,
+        None => {
+            rooms.insert(room.to_string(), client.clone());
+            return;
+        }
+    };
+
+    *waiting.opponent.lock().unwrap() = Some(client.clone());
+    *client.opponent.lock().unwrap() = Some(waiting.clone());
+
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64);
+    for (player, paired) in [&waiting, client].into_iter().enumerate() {
+        // A client that is gone already is noticed by its own thread
+        let _ = paired.send_message(&Message::Start { seed, player });
+    }
+}
+
+fn relay(reader: &mut Reader, client: &Client) -> io::Result<()> {
+    while let Some(message) = reader.read()? {
+        // Anything sent before the opponent shows up is dropped
+        let opponent = client.opponent.lock().unwrap().clone();
+        if let Some(opponent) = opponent {
+            let _ = opponent.send(&message);
+        }
+    }
+    Ok(())
+}
+
+fn leave(rooms: &Rooms, room: &str, client: &Arc<Client>) {
+    {
+        let mut rooms = rooms.lock().unwrap();
+        if rooms
+            .get(room)
+            .is_some_and(|waiting| Arc::ptr_eq(waiting, client))
+        {
+            rooms.remove(room);
+        }
+    }
+
+    let opponent = client.opponent.lock().unwrap().take();
+    if let Some(opponent) = opponent {
+        opponent.opponent.lock().unwrap().take();
+        let _ = opponent.send_message(&Message::Left);
+    }
+}
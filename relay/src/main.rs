@@ -0,0 +1,21 @@
+use std::net::TcpListener;
+use std::process;
+
+/// Where the relay listens unless told otherwise.
+const DEFAULT_ADDR: &str = "127.0.0.1:7878";
+
+fn main() {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_ADDR.to_string());
+    let listener = match TcpListener::bind(&addr) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Unable to listen on {}: {}", addr, err);
+            process::exit(1);
+        }
+    };
+
+    println!("Relaying on {}", addr);
+    relay::serve(listener);
+}
@@ -0,0 +1,205 @@
+//! Telling plain TCP clients apart from WebSocket ones, and framing messages
+//! for each.
+//!
+//! WebSocket clients are spoken to through tungstenite. Their connection is
+//! only ever touched from the client's own thread, which sends whatever the
+//! opponent's thread queued for it in between waiting on the client.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Message, WebSocket};
+
+/// The longest message a client may send before it is dropped. Game messages
+/// are a fraction of this.
+const MAX_MESSAGE_BYTES: usize = 4 * 1024;
+
+/// How much of a WebSocket handshake is read before giving up on the client.
+const MAX_HANDSHAKE_BYTES: usize = 8 * 1024;
+
+/// How long a client has to show which transport it speaks.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a WebSocket client's thread waits on it before sending what was
+/// queued for it.
+const POLL: Duration = Duration::from_millis(5);
+
+/// How long a write to a client may block before the client is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) enum Reader {
+    /// One message per line.
+    Tcp(BufReader<TcpStream>),
+    /// One message per text frame.
+    WebSocket {
+        socket: Box<WebSocket<Capped>>,
+        outgoing: Receiver<String>,
+    },
+}
+
+pub(crate) enum Writer {
+    Tcp(TcpStream),
+    /// Queued for the client's own thread to send.
+    WebSocket(Sender<String>),
+}
+
+/// A client's stream that can only be read so far, so a handshake can't grow
+/// without end.
+pub(crate) struct Capped {
+    stream: TcpStream,
+    left: usize,
+}
+
+impl Read for Capped {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.left == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "WebSocket handshake too long",
+            ));
+        }
+        let len = buf.len().min(self.left);
+        let read = self.stream.read(&mut buf[..len])?;
+        self.left -= read;
+        Ok(read)
+    }
+}
+
+impl Write for Capped {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Answers the WebSocket handshake if the client starts with one.
+pub(crate) fn accept(stream: TcpStream) -> io::Result<(Reader, Writer)> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    // Lines of JSON never start like `GET / HTTP/1.1`
+    let mut first = [0; 1];
+    stream.peek(&mut first)?;
+    if first[0] != b'G' {
+        stream.set_read_timeout(None)?;
+        let writer = Writer::Tcp(stream.try_clone()?);
+        return Ok((Reader::Tcp(BufReader::new(stream)), writer));
+    }
+
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE_BYTES),
+        max_frame_size: Some(MAX_MESSAGE_BYTES),
+        ..WebSocketConfig::default()
+    };
+    let capped = Capped {
+        stream,
+        left: MAX_HANDSHAKE_BYTES,
+    };
+    let mut socket = tungstenite::accept_with_config(capped, Some(config))
+        .map_err(|err| invalid_data(err.to_string()))?;
+    // Frames are held to the config from here on
+    socket.get_mut().left = usize::MAX;
+    socket.get_mut().stream.set_read_timeout(Some(POLL))?;
+
+    let (sender, outgoing) = mpsc::channel();
+    Ok((
+        Reader::WebSocket {
+            socket: Box::new(socket),
+            outgoing,
+        },
+        Writer::WebSocket(sender),
+    ))
+}
+
+fn invalid_data(
+    err: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn into_io(err: tungstenite::Error) -> io::Error {
+    match err {
+        tungstenite::Error::Io(err) => err,
+        err => invalid_data(err),
+    }
+}
+
+impl Reader {
+    /// The next message, or `None` once the client is gone.
+    pub(crate) fn read(&mut self) -> io::Result<Option<String>> {
+        match self {
+            Reader::Tcp(stream) => read_line(stream),
+            Reader::WebSocket { socket, outgoing } => {
+                read_frames(socket, outgoing)
+            }
+        }
+    }
+}
+
+fn read_line(stream: &mut BufReader<TcpStream>) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    // One past the limit, to tell a message that long from a longer one
+    stream
+        .take(MAX_MESSAGE_BYTES as u64 + 1)
+        .read_line(&mut line)?;
+
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.len() > MAX_MESSAGE_BYTES {
+        return Err(invalid_data("message too long"));
+    }
+    line.truncate(line.trim_end().len());
+    Ok(Some(line))
+}
+
+fn read_frames(
+    socket: &mut WebSocket<Capped>,
+    outgoing: &Receiver<String>,
+) -> io::Result<Option<String>> {
+    loop {
+        for message in outgoing.try_iter() {
+            socket
+                .write_message(Message::Text(message))
+                .map_err(into_io)?;
+        }
+
+        match socket.read_message() {
+            Ok(Message::Text(text)) => return Ok(Some(text)),
+            Ok(Message::Binary(data)) => {
+                return String::from_utf8(data).map(Some).map_err(invalid_data)
+            }
+            // Pings are answered and closes seen through by tungstenite
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(
+                tungstenite::Error::ConnectionClosed
+                | tungstenite::Error::AlreadyClosed,
+            ) => return Ok(None),
+            Err(err) => return Err(into_io(err)),
+        }
+    }
+}
+
+impl Writer {
+    pub(crate) fn send(&mut self, message: &str) -> io::Result<()> {
+        match self {
+            Writer::Tcp(stream) => {
+                stream.write_all(message.as_bytes())?;
+                stream.write_all(b"\n")
+            }
+            Writer::WebSocket(sender) => sender
+                .send(message.to_string())
+                .map_err(|_| io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use relay::Message;
+
+fn read_line(reader: &mut impl BufRead) -> String {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    line.trim_end().to_string()
+}
+
+/// Sends a text frame, masked as clients have to.
+fn send_frame(stream: &mut TcpStream, text: &str) {
+    let mask = [1, 2, 3, 4];
+    let mut frame = vec![0x81, 0x80 | text.len() as u8];
+    frame.extend(mask);
+    frame.extend(text.bytes().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+    stream.write_all(&frame).unwrap();
+}
+
+/// Connects to the relay and goes through the WebSocket handshake.
+fn connect_websocket(addr: SocketAddr) -> (TcpStream, BufReader<TcpStream>) {
+    let mut websocket = TcpStream::connect(addr).unwrap();
+    write!(
+        websocket,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+         Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+         Sec-WebSocket-Version: 13\r\n\r\n"
+    )
+    .unwrap();
+    let mut reader = BufReader::new(websocket.try_clone().unwrap());
+    while !read_line(&mut reader).is_empty() {}
+    (websocket, reader)
+}
+
+/// Whether the relay hung up on `stream`, reading past anything it sent
+/// first.
+fn hung_up(stream: &mut impl Read) -> bool {
+    let mut buf = [0; 256];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => return true,
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::ConnectionReset => {
+                return true
+            }
+            Err(_) => return false,
+        }
+    }
+}
+
+fn read_frame(reader: &mut impl Read) -> String {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head).unwrap();
+    assert_eq!(head[0], 0x81, "not a whole text frame");
+    let mut payload = vec![0; head[1] as usize];
+    reader.read_exact(&mut payload).unwrap();
+    String::from_utf8(payload).unwrap()
+}
+
+#[test]
+fn websocket_and_tcp_clients_are_paired_and_relayed() {
+    let addr = relay::spawn_local().unwrap();
+
+    // The handshake from RFC 6455
+    let mut websocket = TcpStream::connect(addr).unwrap();
+    write!(
+        websocket,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+         Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+         Sec-WebSocket-Version: 13\r\n\r\n"
+    )
+    .unwrap();
+    let mut websocket_reader = BufReader::new(websocket.try_clone().unwrap());
+    let mut response = Vec::new();
+    loop {
+        let line = read_line(&mut websocket_reader);
+        if line.is_empty() {
+            break;
+        }
+        response.push(line);
+    }
+    assert!(response[0].contains("101"), "{:?}", response);
+    // Header names go in any case
+    assert!(
+        response.iter().any(|header| header.eq_ignore_ascii_case(
+            "Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        )),
+        "{:?}",
+        response
+    );
+    send_frame(&mut websocket, r#"{"join":{"room":"test"}}"#);
+    // Waiting in the room first, so it is numbered 0
+    thread::sleep(Duration::from_millis(100));
+
+    let mut tcp = TcpStream::connect(addr).unwrap();
+    writeln!(tcp, r#"{{"join":{{"room":"test"}}}}"#).unwrap();
+    let mut tcp_reader = BufReader::new(tcp.try_clone().unwrap());
+
+    let first: Message =
+        serde_json::from_str(&read_frame(&mut websocket_reader)).unwrap();
+    let second: Message =
+        serde_json::from_str(&read_line(&mut tcp_reader)).unwrap();
+    let (first_seed, second_seed) = match (first, second) {
+        (
+            Message::Start {
+                seed: first_seed,
+                player: 0,
+            },
+            Message::Start {
+                seed: second_seed,
+                player: 1,
+            },
+        ) => (first_seed, second_seed),
+        other => panic!("expected both to start, got {:?}", other),
+    };
+    assert_eq!(first_seed, second_seed);
+
+    send_frame(&mut websocket, r#"{"hello":"tcp"}"#);
+    assert_eq!(read_line(&mut tcp_reader), r#"{"hello":"tcp"}"#);
+    writeln!(tcp, r#"{{"hello":"websocket"}}"#).unwrap();
+    assert_eq!(
+        read_frame(&mut websocket_reader),
+        r#"{"hello":"websocket"}"#
+    );
+
+    drop(websocket_reader);
+    drop(websocket);
+    assert_eq!(read_line(&mut tcp_reader), r#""left""#);
+}
+
+#[test]
+fn clients_breaking_the_limits_are_dropped() {
+    let addr = relay::spawn_local().unwrap();
+    let timeout = Some(Duration::from_secs(5));
+
+    // Unmasked, which only servers may send
+    let (mut unmasked, mut unmasked_reader) = connect_websocket(addr);
+    unmasked_reader.get_ref().set_read_timeout(timeout).unwrap();
+    let join = br#"{"join":{"room":"test"}}"#;
+    let mut frame = vec![0x81, join.len() as u8];
+    frame.extend(join);
+    unmasked.write_all(&frame).unwrap();
+    assert!(hung_up(&mut unmasked_reader), "unmasked frame accepted");
+
+    // Claiming more than could ever be allocated
+    let (mut huge, mut huge_reader) = connect_websocket(addr);
+    huge_reader.get_ref().set_read_timeout(timeout).unwrap();
+    let mut frame = vec![0x81, 0x80 | 127];
+    frame.extend(u64::MAX.to_be_bytes());
+    frame.extend([1, 2, 3, 4]);
+    huge.write_all(&frame).unwrap();
+    assert!(hung_up(&mut huge_reader), "huge frame accepted");
+
+    // A line that never ends
+    let mut tcp = TcpStream::connect(addr).unwrap();
+    tcp.set_read_timeout(timeout).unwrap();
+    // Fails once the relay hangs up
+    let _ = tcp.write_all(&[b'x'; 64 * 1024]);
+    assert!(hung_up(&mut tcp), "endless line accepted");
+}
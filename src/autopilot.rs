@@ -0,0 +1,275 @@
+//! A scripted pilot that flies the bird on its own, for the menu's attract
+//! mode and to check that the hardest the course gets is still survivable.
+//!
+//! Every tick it looks ahead at the obstacles in sight and only flaps if that
+//! keeps a way through them open, heading for the next gap when either would.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy::time::Stopwatch;
+use bevy_rapier2d::prelude::*;
+
+use crate::config::GameConfig;
+use crate::controls::ActionButton;
+use crate::difficulty::Difficulty;
+use crate::env::{Action, GapQuery, NextObstacle, Observation, PlayerQuery};
+use crate::replay::TICK_SECS;
+use crate::{
+    player_mass, ActionKey, AppState, FlapRequest, GameMode, WelcomeText,
+};
+
+/// How long the menu sits untouched before the attract mode demo starts.
+pub const DEMO_DELAY_SECS: f32 = 10.0;
+
+/// How many ticks ahead the pilot plans, enough to see the next pair of
+/// obstacles through at the hardest difficulty.
+const HORIZON: u32 = 150;
+/// How many moves the pilot tries before giving up on a plan.
+const BUDGET: u32 = 20_000;
+/// Space kept between the bird and anything it could touch.
+const MARGIN: f32 = 3.0;
+/// Where the floor and ceiling colliders start.
+const BOUNDS_Y: f32 = 700.0;
+
+/// Flies the player's bird while present.
+pub struct Autopilot;
+
+/// How the bird and the obstacles move, in 'pixels' and 'seconds'.
+struct Physics {
+    /// Upward speed added by a flap.
+    kick: f32,
+    gravity: f32,
+    radius: f32,
+    /// Half the width of an obstacle.
+    half_width: f32,
+    scroll_speed: f32,
+}
+
+impl Physics {
+    /// Whether the bird fits at `y` `tick`s from now.
+    fn clear(&self, obstacles: &[NextObstacle], tick: u32, y: f32) -> bool {
+        if (y.abs() + self.radius) > BOUNDS_Y - MARGIN {
+            return false;
+        }
+
+        obstacles.iter().all(|obstacle| {
+            let distance =
+                obstacle.distance + self.scroll_speed * tick as f32 * TICK_SECS;
+            // The bird is round, so it can already be level with the edge of
+            // an obstacle before it's all the way through the gap
+            let past_edge = (distance.abs() - self.half_width).max(0.0);
+            if past_edge >= self.radius {
+                return true;
+            }
+            let reach = (self.radius.powi(2) - past_edge.powi(2)).sqrt();
+
+            let gap = obstacle.gap_in(tick as u64);
+            let half_gap = gap.size / 2.0 - MARGIN;
+            y - reach > gap.y - half_gap && y + reach < gap.y + half_gap
+        })
+    }
+}
+
+/// A search for flaps that keep the bird clear of everything in sight.
+struct Plan<'a> {
+    physics: &'a Physics,
+    obstacles: &'a [NextObstacle],
+    target_y: f32,
+    /// States already known to end in a crash, rounded to a pixel and
+    /// 5 'pixels/second'.
+    doomed: HashSet<(u32, i32, i32)>,
+    budget: u32,
+}
+
+impl Plan<'_> {
+    fn preferred(&self, y: f32, velocity: f32) -> bool {
+        y < self.target_y && velocity < 0.0
+    }
+
+    /// Whether the bird at `y` going at `velocity` gets through the next
+    /// `tick`s, flapping first if `flap`.
+    fn survives(
+        &mut self,
+        tick: u32,
+        y: f32,
+        velocity: f32,
+        flap: bool,
+    ) -> bool {
+        if self.budget == 0 {
+            return false;
+        }
+        self.budget -= 1;
+
+        let kick = if flap { self.physics.kick } else { 0.0 };
+        let velocity = velocity + kick - self.physics.gravity * TICK_SECS;
+        let y = y + velocity * TICK_SECS;
+        let tick = tick + 1;
+        if !self.physics.clear(self.obstacles, tick, y) {
+            return false;
+        }
+        if tick >= HORIZON {
+            return true;
+        }
+
+        let state = (tick, y.round() as i32, (velocity / 5.0).round() as i32);
+        if self.doomed.contains(&state) {
+            return false;
+        }
+
+        // Flapping on the way up only ever overshoots, so don't try it
+        let preferred = self.preferred(y, velocity);
+        let survives = self.survives(tick, y, velocity, preferred)
+            || ((preferred || velocity < 0.0)
+                && self.survives(tick, y, velocity, !preferred));
+        // Running out of budget says nothing about the state itself
+        if !survives && self.budget > 0 {
+            self.doomed.insert(state);
+        }
+        survives
+    }
+}
+
+fn decide(physics: &Physics, observation: &Observation) -> Action {
+    let obstacles: Vec<NextObstacle> =
+        observation.obstacles.iter().flatten().copied().collect();
+    // Head for the gap the bird isn't mostly through yet, where it will be
+    // once the bird gets there
+    let target_y = obstacles
+        .iter()
+        .find(|obstacle| obstacle.distance > -physics.half_width)
+        .map_or(0.0, |obstacle| {
+            let arrival = (obstacle.distance - physics.half_width).max(0.0)
+                / -physics.scroll_speed
+                / TICK_SECS;
+            obstacle.gap_in(arrival as u64).y
+        });
+
+    let mut plan = Plan {
+        physics,
+        obstacles: &obstacles,
+        target_y,
+        doomed: HashSet::new(),
+        budget: BUDGET,
+    };
+    let (y, velocity) = (observation.bird_y, observation.bird_velocity);
+    let preferred = plan.preferred(y, velocity);
+    let flap = if plan.survives(0, y, velocity, preferred) {
+        preferred
+    } else {
+        plan.budget = BUDGET;
+        if plan.survives(0, y, velocity, !preferred) {
+            !preferred
+        } else {
+            // With no way through either, keep heading for the gap
+            preferred
+        }
+    };
+
+    if flap {
+        Action::Flap
+    } else {
+        Action::Noop
+    }
+}
+
+/// Asks for a flap whenever the autopilot wants one, before the player moves.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn fly(
+    autopilot: Option<Res<Autopilot>>,
+    config: Res<GameConfig>,
+    difficulty: Res<Difficulty>,
+    rapier_config: Res<RapierConfiguration>,
+    rapier_context: Res<RapierContext>,
+    player_query: PlayerQuery,
+    gap_query: GapQuery,
+    mut flap_request: ResMut<FlapRequest>,
+) {
+    if autopilot.is_none() {
+        return;
+    }
+
+    let physics = Physics {
+        kick: config.impulse / player_mass(&config, &rapier_context),
+        gravity: rapier_config.gravity.y.abs() * config.gravity_scale,
+        radius: config.sprite_size / 2.0,
+        half_width: config.obstacle_width,
+        scroll_speed: difficulty.scroll_speed,
+    };
+    let observation = Observation::observe(&config, &player_query, &gap_query);
+    if decide(&physics, &observation) == Action::Flap {
+        flap_request.0 = true;
+    }
+}
+
+/// Starts the attract mode demo once the menu has been left alone for
+/// [`DEMO_DELAY_SECS`].
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn start_demo(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut idle: Local<Stopwatch>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    action_key: Option<Res<ActionKey>>,
+    mut mode: ResMut<GameMode>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if keyboard_input.get_pressed().next().is_some()
+        || mouse_input.get_pressed().next().is_some()
+    {
+        idle.reset();
+        return;
+    }
+
+    // The other modes have a course of their own picked already
+    idle.tick(time.delta());
+    if *mode != GameMode::Endless || idle.elapsed_secs() < DEMO_DELAY_SECS {
+        return;
+    }
+    idle.reset();
+
+    info!("Starting the attract mode demo");
+    *mode = GameMode::Demo;
+    commands.insert_resource(Autopilot);
+    if action_key.is_none() {
+        // Nothing presses it, but the game expects one to be picked
+        commands.insert_resource(ActionKey(ActionButton::Key(KeyCode::Space)));
+    }
+    state.set(AppState::InGame).unwrap();
+}
+
+/// Goes back to the menu as soon as anyone touches the game during the
+/// demo, or the autopilot dies.
+pub(crate) fn end_demo(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    text_query: Query<Entity, With<WelcomeText>>,
+    mut mode: ResMut<GameMode>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if *mode != GameMode::Demo {
+        return;
+    }
+
+    let touched = keyboard_input.get_just_pressed().next().is_some()
+        || mouse_input.get_just_pressed().next().is_some();
+    if !touched && *state.current() != AppState::GameOver {
+        return;
+    }
+
+    stop_demo(&mut commands, &mut mode);
+    // The menu puts up its own
+    for entity in &text_query {
+        commands.entity(entity).despawn();
+    }
+    // Wins over pausing or dying on the same frame
+    state.overwrite_replace(AppState::Menu).unwrap();
+}
+
+/// Hands the game back from the demo.
+pub(crate) fn stop_demo(commands: &mut Commands, mode: &mut GameMode) {
+    *mode = GameMode::Endless;
+    commands.remove_resource::<Autopilot>();
+}
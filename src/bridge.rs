@@ -0,0 +1,214 @@
+//! Lets a program outside the game play it over a local TCP socket, so agents
+//! written in any language can drive the real native build.
+//!
+//! The protocol is JSON, one message per line. Every tick of a run the game
+//! sends what the bird sees, the same as [`Observation`]:
+//!
+//! ```text
+//! {"tick":42,"score":1,"done":false,"bird_y":-12.5,"bird_velocity":-80.0,
+//!  "obstacles":[{"distance":230.0,"gap_y":40.0,"gap_size":380.0},null]}
+//! ```
+//!
+//! followed by a last message with `"done":true` when the bird dies. The agent
+//! sends `"flap"` to flap on the next tick, and `"restart"` to start a new run
+//! from the menu or after dying.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::autopilot;
+use crate::config::GameConfig;
+use crate::controls::ActionButton;
+use crate::env::{GapQuery, Observation, PlayerQuery};
+use crate::replay::RunTick;
+use crate::{ActionKey, AppState, FlapRequest, GameMode, Score};
+
+/// How many lines an agent can fall behind by before observations are dropped
+/// for it.
+const AGENT_BACKLOG: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    Flap,
+    Restart,
+}
+
+#[derive(Serialize)]
+struct Message {
+    tick: u64,
+    score: u128,
+    done: bool,
+    #[serde(flatten)]
+    observation: Observation,
+}
+
+/// The agents connected to the game.
+pub(crate) struct Bridge {
+    commands: Mutex<Receiver<Command>>,
+    /// Lines for each agent, written out by a thread of its own so an agent
+    /// that stops reading can't hold up the game.
+    agents: Arc<Mutex<Vec<SyncSender<String>>>>,
+    /// Whether the end of the last run was sent already.
+    sent_done: bool,
+}
+
+/// Listens for agents on `addr` and returns the address it got, which tells
+/// the port picked when asked for port 0.
+pub fn serve(
+    app: &mut App,
+    addr: impl ToSocketAddrs,
+) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let (sender, receiver) = mpsc::channel();
+    let agents = Arc::new(Mutex::new(Vec::new()));
+
+    let accepted = agents.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream.and_then(|stream| Ok((stream.try_clone()?, stream))) {
+                Ok((reader, writer)) => {
+                    info!("Agent connected from {:?}", writer.peer_addr());
+                    let (lines, queued) = mpsc::sync_channel(AGENT_BACKLOG);
+                    accepted.lock().unwrap().push(lines);
+                    thread::spawn(move || write_lines(writer, queued));
+                    let sender = sender.clone();
+                    thread::spawn(move || read_commands(reader, sender));
+                }
+                Err(err) => warn!("Unable to accept agent: {}", err),
+            }
+        }
+    });
+
+    info!("Waiting for agents on {}", local_addr);
+    app.insert_resource(Bridge {
+        commands: Mutex::new(receiver),
+        agents,
+        sent_done: false,
+    });
+    Ok(local_addr)
+}
+
+fn read_commands(stream: TcpStream, sender: Sender<Command>) {
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        match serde_json::from_str(&line) {
+            Ok(command) => {
+                if sender.send(command).is_err() {
+                    break;
+                }
+            }
+            Err(err) => warn!("Ignoring agent command {:?}: {}", line, err),
+        }
+    }
+}
+
+fn write_lines(mut stream: TcpStream, lines: Receiver<String>) {
+    for line in lines {
+        if let Err(err) = stream.write_all(line.as_bytes()) {
+            info!("Agent disconnected: {}", err);
+            break;
+        }
+    }
+}
+
+/// Hands the agents' commands to the game, before the player moves.
+pub(crate) fn apply_commands(
+    mut commands: Commands,
+    bridge: Option<Res<Bridge>>,
+    mut flap_request: ResMut<FlapRequest>,
+    mut mode: ResMut<GameMode>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let bridge = match bridge {
+        Some(bridge) => bridge,
+        None => return,
+    };
+
+    let received: Vec<Command> =
+        bridge.commands.lock().unwrap().try_iter().collect();
+    for command in received {
+        match command {
+            Command::Flap => flap_request.0 = true,
+            Command::Restart => {
+                let result = if *state.current() == AppState::Menu {
+                    // Nothing presses it, but the game expects one
+                    commands.insert_resource(ActionKey(ActionButton::Key(
+                        KeyCode::Space,
+                    )));
+                    state.set(AppState::InGame)
+                } else {
+                    // The agent takes over from the attract mode demo
+                    if *mode == GameMode::Demo {
+                        autopilot::stop_demo(&mut commands, &mut mode);
+                    }
+                    state.replace(AppState::Restarting)
+                };
+
+                if let Err(err) = result {
+                    warn!("Unable to restart for agent: {:?}", err);
+                }
+            }
+        }
+    }
+}
+
+/// Sends every agent what the bird sees once the tick has been simulated.
+pub(crate) fn send_observation(
+    bridge: Option<ResMut<Bridge>>,
+    state: Res<State<AppState>>,
+    tick: Res<RunTick>,
+    score: Res<Score>,
+    config: Res<GameConfig>,
+    player_query: PlayerQuery,
+    gap_query: GapQuery,
+) {
+    let mut bridge = match bridge {
+        Some(bridge) => bridge,
+        None => return,
+    };
+
+    let done = match state.current() {
+        AppState::InGame => false,
+        AppState::GameOver if !bridge.sent_done => true,
+        _ => return,
+    };
+    bridge.sent_done = done;
+
+    let message = Message {
+        tick: tick.0,
+        score: score.0,
+        done,
+        observation: Observation::observe(&config, &player_query, &gap_query),
+    };
+    let mut line = match serde_json::to_string(&message) {
+        Ok(line) => line,
+        Err(err) => {
+            warn!("Unable to encode observation: {}", err);
+            return;
+        }
+    };
+    line.push('\n');
+
+    bridge.agents.lock().unwrap().retain(|agent| {
+        match agent.try_send(line.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                debug!("Agent isn't keeping up, dropping an observation");
+                true
+            }
+            // Its writer is gone, so is the agent
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    });
+}
@@ -0,0 +1,371 @@
+//! Gameplay tuning, loaded from a RON asset so it can be changed without a
+//! rebuild. Edits to the file are picked up while the game is running.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use bevy::asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use serde::{Deserialize, Serialize};
+
+use bevy_rapier2d::prelude::*;
+
+use crate::difficulty::{DifficultyPreset, Progress};
+use crate::obstacle::ObstacleWeights;
+use crate::{Background, Player};
+
+/// Where the config is loaded from, relative to the asset folder.
+pub const DEFAULT_CONFIG_PATH: &str = "game.config.ron";
+
+/// Every number that decides how the game feels.
+///
+/// Fields left out of the config file keep their default value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TypeUuid)]
+#[serde(default, deny_unknown_fields)]
+#[uuid = "5f0d3b0e-7d4c-4c36-9a0b-5b1f4e0c2a71"]
+pub struct GameConfig {
+    /// Window width in pixels.
+    pub width: f32,
+    /// Window height in pixels.
+    pub height: f32,
+    /// Half the width of an obstacle collider.
+    pub obstacle_width: f32,
+    /// Horizontal speed of the obstacles in 'pixels/second' at the start of
+    /// a run.
+    pub scroll_speed: f32,
+    /// Upward impulse applied on every flap.
+    pub impulse: f32,
+    /// Density of the player collider.
+    pub density: f32,
+    /// Multiplier applied to gravity for the player.
+    pub gravity_scale: f32,
+    /// Width and height of the player sprite.
+    pub sprite_size: f32,
+    /// Seconds between two obstacle spawns at the start of a run.
+    pub spawn_interval_secs: f32,
+    /// How far up or down a pair of obstacles can be shifted at random.
+    pub random_offset: f32,
+    /// Space between a pair of obstacles at the start of a run.
+    pub gap_size: f32,
+    /// How quickly a run gets harder.
+    pub difficulty: DifficultyPreset,
+    /// What the difficulty curve measures progress in.
+    pub difficulty_progress: Progress,
+    /// Space between a pair of obstacles once the curve tops out.
+    pub hardest_gap_size: f32,
+    /// Speed of the obstacles once the curve tops out.
+    pub hardest_scroll_speed: f32,
+    /// Seconds between two obstacle spawns once the curve tops out.
+    pub hardest_spawn_interval_secs: f32,
+    /// The smallest gap allowed, as a multiple of the player's size.
+    pub min_gap_clearance: f32,
+    /// How often each kind of moving obstacle turns up at the start of a run.
+    pub obstacle_weights: ObstacleWeights,
+    /// How often each kind of moving obstacle turns up once the curve tops
+    /// out.
+    pub hardest_obstacle_weights: ObstacleWeights,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            width: 500.0,
+            height: 1000.0,
+            obstacle_width: 50.0,
+            scroll_speed: -100.0,
+            impulse: 25000.0,
+            density: 50.0,
+            gravity_scale: 10.0,
+            sprite_size: 100.0,
+            spawn_interval_secs: 3.0,
+            random_offset: 150.0,
+            gap_size: 400.0,
+            difficulty: DifficultyPreset::Linear { full_at: 20.0 },
+            difficulty_progress: Progress::Score,
+            hardest_gap_size: 180.0,
+            hardest_scroll_speed: -160.0,
+            hardest_spawn_interval_secs: 2.0,
+            min_gap_clearance: 1.5,
+            obstacle_weights: ObstacleWeights::default(),
+            hardest_obstacle_weights: ObstacleWeights {
+                still: 2.0,
+                bobbing: 1.0,
+                breathing: 1.0,
+                sliding: 1.0,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Parse(ron::Error),
+    Invalid {
+        field: &'static str,
+        value: f32,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Parse(err) => {
+                write!(f, "invalid game config: {}", err)
+            }
+            ConfigError::Invalid {
+                field,
+                value,
+                expected,
+            } => write!(
+                f,
+                "invalid game config: `{}` is {} but must be {}",
+                field, value, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl GameConfig {
+    /// Parses and validates a config written in RON.
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, ConfigError> {
+        let config: GameConfig =
+            ron::de::from_bytes(bytes).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that every field holds a value the game can run with.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let positive = [
+            ("width", self.width),
+            ("height", self.height),
+            ("obstacle_width", self.obstacle_width),
+            ("impulse", self.impulse),
+            ("density", self.density),
+            ("sprite_size", self.sprite_size),
+            ("spawn_interval_secs", self.spawn_interval_secs),
+            ("gap_size", self.gap_size),
+            ("hardest_gap_size", self.hardest_gap_size),
+            (
+                "hardest_spawn_interval_secs",
+                self.hardest_spawn_interval_secs,
+            ),
+        ];
+        let non_negative = [
+            ("gravity_scale", self.gravity_scale),
+            ("random_offset", self.random_offset),
+        ];
+        let negative = [
+            ("scroll_speed", self.scroll_speed),
+            ("hardest_scroll_speed", self.hardest_scroll_speed),
+        ];
+
+        for (field, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(ConfigError::Invalid {
+                    field,
+                    value,
+                    expected: "greater than zero",
+                });
+            }
+        }
+
+        for (field, value) in non_negative {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(ConfigError::Invalid {
+                    field,
+                    value,
+                    expected: "zero or more",
+                });
+            }
+        }
+
+        for (field, value) in negative {
+            if !(value.is_finite() && value < 0.0) {
+                return Err(ConfigError::Invalid {
+                    field,
+                    value,
+                    expected: "less than zero",
+                });
+            }
+        }
+
+        if !(self.min_gap_clearance.is_finite()
+            && self.min_gap_clearance >= 1.0)
+        {
+            return Err(ConfigError::Invalid {
+                field: "min_gap_clearance",
+                value: self.min_gap_clearance,
+                expected: "at least 1",
+            });
+        }
+
+        self.validate_difficulty()?;
+        self.validate_obstacle_weights()
+    }
+
+    fn validate_difficulty(&self) -> Result<(), ConfigError> {
+        let (full_at, cap) = match self.difficulty {
+            DifficultyPreset::Linear { full_at }
+            | DifficultyPreset::Stepped { full_at, .. }
+            | DifficultyPreset::Logarithmic { full_at } => (full_at, 1.0),
+            DifficultyPreset::Capped { full_at, cap } => (full_at, cap),
+        };
+
+        if !(full_at.is_finite() && full_at > 0.0) {
+            return Err(ConfigError::Invalid {
+                field: "difficulty.full_at",
+                value: full_at,
+                expected: "greater than zero",
+            });
+        }
+
+        if !(0.0..=1.0).contains(&cap) {
+            return Err(ConfigError::Invalid {
+                field: "difficulty.cap",
+                value: cap,
+                expected: "between 0 and 1",
+            });
+        }
+
+        Ok(())
+    }
+
+    fn validate_obstacle_weights(&self) -> Result<(), ConfigError> {
+        for (weights, total_field) in [
+            (&self.obstacle_weights, "obstacle_weights"),
+            (&self.hardest_obstacle_weights, "hardest_obstacle_weights"),
+        ] {
+            for (field, value) in weights.fields() {
+                if !(value.is_finite() && value >= 0.0) {
+                    return Err(ConfigError::Invalid {
+                        field,
+                        value,
+                        expected: "zero or more",
+                    });
+                }
+            }
+
+            let total = weights.fields().iter().map(|(_, value)| value).sum();
+            if total <= 0.0 {
+                return Err(ConfigError::Invalid {
+                    field: total_field,
+                    value: total,
+                    expected: "to add up to more than zero",
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The smallest gap the player can still fly through.
+    pub fn min_gap_size(&self) -> f32 {
+        // The player collider is a ball as wide as the sprite
+        self.sprite_size * self.min_gap_clearance
+    }
+}
+
+#[derive(Default)]
+pub struct GameConfigLoader;
+
+impl AssetLoader for GameConfigLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let config = GameConfig::from_ron(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(config));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["config.ron"]
+    }
+}
+
+/// The config file to load, relative to the asset folder or absolute.
+pub struct GameConfigPath(pub PathBuf);
+
+impl Default for GameConfigPath {
+    fn default() -> Self {
+        GameConfigPath(DEFAULT_CONFIG_PATH.into())
+    }
+}
+
+pub(crate) struct GameConfigHandle(Handle<GameConfig>);
+
+pub(crate) fn load_game_config(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    path: Res<GameConfigPath>,
+) {
+    info!("Loading game config from {}", path.0.display());
+    let handle = asset_server.load(path.0.as_path());
+    commands.insert_resource(GameConfigHandle(handle));
+}
+
+/// Swaps in the loaded config once it's ready, and again every time the file
+/// changes. If it fails to load, the previous values stay in place.
+pub(crate) fn apply_game_config(
+    mut config_evr: EventReader<AssetEvent<GameConfig>>,
+    handle: Option<Res<GameConfigHandle>>,
+    configs: Res<Assets<GameConfig>>,
+    mut config: ResMut<GameConfig>,
+    mut windows: ResMut<Windows>,
+) {
+    let handle = match handle {
+        Some(handle) => handle,
+        None => return,
+    };
+
+    for event in config_evr.iter() {
+        if let AssetEvent::Created { handle: loaded }
+        | AssetEvent::Modified { handle: loaded } = event
+        {
+            if *loaded != handle.0 {
+                continue;
+            }
+
+            if let Some(loaded) = configs.get(loaded) {
+                info!("Applying game config: {:?}", loaded);
+                *config = loaded.clone();
+                if let Some(window) = windows.get_primary_mut() {
+                    window.set_resolution(config.width, config.height);
+                }
+            }
+        }
+    }
+}
+
+/// Brings the entities already in play in line with the current config.
+/// Obstacles follow the difficulty curve, which picks up config changes on its
+/// own.
+pub(crate) fn retune_entities(
+    config: Res<GameConfig>,
+    mut background_query: Query<&mut Velocity, With<Background>>,
+    mut player_query: Query<
+        (&mut GravityScale, &mut ColliderMassProperties),
+        With<Player>,
+    >,
+) {
+    if !config.is_changed() {
+        return;
+    }
+
+    for mut velocity in &mut background_query {
+        velocity.linvel.x = 0.1 * config.scroll_speed;
+    }
+
+    for (mut gravity_scale, mut mass_properties) in &mut player_query {
+        gravity_scale.0 = config.gravity_scale;
+        *mass_properties = ColliderMassProperties::Density(config.density);
+    }
+}
@@ -0,0 +1,607 @@
+//! The buttons the game can be played with, on any device at hand: keyboard,
+//! gamepad, mouse or touch screen, and the screen to rebind them on.
+//!
+//! Bindings are kept in [`GameStorage`], so they only have to be set up once.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier2d::render::DebugRenderContext;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::GameStorage;
+use crate::{AppState, HOVERED_BUTTON, NORMAL_BUTTON};
+
+const STORAGE_KEY: &str = "bindings";
+
+/// A button on one of the devices the game reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActionButton {
+    Key(KeyCode),
+    /// The same button on any gamepad.
+    Gamepad(GamepadButtonType),
+    Mouse(MouseButton),
+    /// A tap anywhere on a touch screen.
+    Touch,
+}
+
+impl ActionButton {
+    /// Whether the button is pressed on a spot of the screen, where it could
+    /// also hit a button of the UI.
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, ActionButton::Mouse(_) | ActionButton::Touch)
+    }
+}
+
+impl fmt::Display for ActionButton {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionButton::Key(key) => write!(f, "{:?}", key),
+            ActionButton::Gamepad(button) => write!(f, "Gamepad {:?}", button),
+            ActionButton::Mouse(button) => write!(f, "Mouse {:?}", button),
+            ActionButton::Touch => write!(f, "Touch"),
+        }
+    }
+}
+
+/// Something the player can do with a button.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum GameAction {
+    Flap,
+    Pause,
+    Restart,
+    /// Back to the menu from a finished run.
+    Menu,
+    /// Shows the colliders.
+    ToggleDebug,
+}
+
+impl GameAction {
+    pub const ALL: [GameAction; 5] = [
+        GameAction::Flap,
+        GameAction::Pause,
+        GameAction::Restart,
+        GameAction::Menu,
+        GameAction::ToggleDebug,
+    ];
+}
+
+impl fmt::Display for GameAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GameAction::Flap => "Flap",
+            GameAction::Pause => "Pause",
+            GameAction::Restart => "Restart",
+            GameAction::Menu => "Menu",
+            GameAction::ToggleDebug => "Toggle debug",
+        })
+    }
+}
+
+/// Which buttons trigger each action. The action button picked on the menu
+/// flaps on top of these.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputBindings(BTreeMap<GameAction, Vec<ActionButton>>);
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        use ActionButton::{Gamepad, Key};
+
+        InputBindings(BTreeMap::from([
+            (
+                GameAction::Flap,
+                vec![Key(KeyCode::W), Key(KeyCode::Up), Key(KeyCode::Space)],
+            ),
+            (
+                GameAction::Pause,
+                vec![
+                    Key(KeyCode::Escape),
+                    Key(KeyCode::P),
+                    Gamepad(GamepadButtonType::Start),
+                ],
+            ),
+            (
+                GameAction::Restart,
+                vec![Key(KeyCode::Return), Key(KeyCode::R)],
+            ),
+            (
+                GameAction::Menu,
+                vec![Key(KeyCode::Escape), Gamepad(GamepadButtonType::Select)],
+            ),
+            (GameAction::ToggleDebug, vec![Key(KeyCode::F3)]),
+        ]))
+    }
+}
+
+impl InputBindings {
+    pub fn buttons(&self, action: GameAction) -> &[ActionButton] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds `button` to the ones that trigger `action`.
+    pub fn bind(&mut self, action: GameAction, button: ActionButton) {
+        let buttons = self.0.entry(action).or_default();
+        if !buttons.contains(&button) {
+            buttons.push(button);
+        }
+    }
+
+    /// Leaves `action` without any button.
+    pub fn clear(&mut self, action: GameAction) {
+        self.0.remove(&action);
+    }
+
+    pub(crate) fn just_pressed(
+        &self,
+        action: GameAction,
+        buttons: &Buttons,
+    ) -> bool {
+        self.buttons(action)
+            .iter()
+            .any(|&button| buttons.just_pressed(button))
+    }
+
+    fn load(storage: &GameStorage) -> Self {
+        storage
+            .load(STORAGE_KEY)
+            .and_then(|saved| match ron::from_str(&saved) {
+                Ok(bindings) => Some(bindings),
+                Err(err) => {
+                    warn!("Discarding unreadable bindings: {}", err);
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    fn save(&self, storage: &GameStorage) {
+        let result = ron::to_string(self)
+            .map_err(|err| err.to_string())
+            .and_then(|saved| {
+                storage
+                    .save(STORAGE_KEY, &saved)
+                    .map_err(|err| err.to_string())
+            });
+
+        if let Err(err) = result {
+            warn!("Unable to save bindings: {}", err);
+        }
+    }
+}
+
+/// The state of every device's buttons this frame.
+#[derive(SystemParam)]
+pub(crate) struct Buttons<'w, 's> {
+    keyboard: ResMut<'w, Input<KeyCode>>,
+    gamepad: ResMut<'w, Input<GamepadButton>>,
+    mouse: ResMut<'w, Input<MouseButton>>,
+    touches: Res<'w, Touches>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl Buttons<'_, '_> {
+    pub(crate) fn just_pressed(&self, button: ActionButton) -> bool {
+        match button {
+            ActionButton::Key(key) => self.keyboard.just_pressed(key),
+            ActionButton::Gamepad(button_type) => self
+                .gamepad
+                .get_just_pressed()
+                .any(|pressed| pressed.button_type == button_type),
+            ActionButton::Mouse(button) => self.mouse.just_pressed(button),
+            ActionButton::Touch => self.touches.any_just_pressed(),
+        }
+    }
+
+    /// Stops anything else from reading this frame's press of `button`. A
+    /// tap can't be taken back.
+    pub(crate) fn clear_just_pressed(&mut self, button: ActionButton) {
+        match button {
+            ActionButton::Key(key) => {
+                self.keyboard.clear_just_pressed(key);
+            }
+            ActionButton::Gamepad(button_type) => {
+                let pressed: Vec<GamepadButton> = self
+                    .gamepad
+                    .get_just_pressed()
+                    .filter(|pressed| pressed.button_type == button_type)
+                    .copied()
+                    .collect();
+                for button in pressed {
+                    self.gamepad.clear_just_pressed(button);
+                }
+            }
+            ActionButton::Mouse(button) => {
+                self.mouse.clear_just_pressed(button);
+            }
+            ActionButton::Touch => {}
+        }
+    }
+
+    /// Every button pressed this frame, on any device.
+    pub(crate) fn all_just_pressed(
+        &self,
+    ) -> impl Iterator<Item = ActionButton> + '_ {
+        let touch = self.touches.any_just_pressed().then_some(());
+
+        self.keyboard
+            .get_just_pressed()
+            .map(|&key| ActionButton::Key(key))
+            .chain(
+                self.gamepad
+                    .get_just_pressed()
+                    .map(|button| ActionButton::Gamepad(button.button_type)),
+            )
+            .chain(
+                self.mouse
+                    .get_just_pressed()
+                    .map(|&button| ActionButton::Mouse(button)),
+            )
+            .chain(touch.map(|_| ActionButton::Touch))
+    }
+
+    /// Every button let go of this frame, on any device.
+    pub(crate) fn all_just_released(
+        &self,
+    ) -> impl Iterator<Item = ActionButton> + '_ {
+        let touch = self.touches.any_just_released().then_some(());
+
+        self.keyboard
+            .get_just_released()
+            .map(|&key| ActionButton::Key(key))
+            .chain(
+                self.gamepad
+                    .get_just_released()
+                    .map(|button| ActionButton::Gamepad(button.button_type)),
+            )
+            .chain(
+                self.mouse
+                    .get_just_released()
+                    .map(|&button| ActionButton::Mouse(button)),
+            )
+            .chain(touch.map(|_| ActionButton::Touch))
+    }
+}
+
+pub(crate) fn load_bindings(
+    mut bindings: ResMut<InputBindings>,
+    storage: Res<GameStorage>,
+) {
+    *bindings = InputBindings::load(&storage);
+}
+
+/// Shows or hides the colliders, when there's a debug renderer to do it.
+pub(crate) fn toggle_debug(
+    bindings: Res<InputBindings>,
+    buttons: Buttons,
+    state: Res<State<AppState>>,
+    debug_context: Option<ResMut<DebugRenderContext>>,
+) {
+    // The controls screen reads every button for rebinding
+    if *state.current() == AppState::Controls
+        || !bindings.just_pressed(GameAction::ToggleDebug, &buttons)
+    {
+        return;
+    }
+
+    if let Some(mut debug_context) = debug_context {
+        debug_context.enabled = !debug_context.enabled;
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct ControlsButton;
+
+type ControlsButtonInteraction = (Changed<Interaction>, With<ControlsButton>);
+
+/// Opens the controls screen from the menu with F1 or a click.
+pub(crate) fn spawn_controls_button(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(30.0),
+                    left: Val::Px(50.0),
+                    ..default()
+                },
+                size: Size::new(Val::Px(400.0), Val::Px(70.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: NORMAL_BUTTON.into(),
+            ..default()
+        })
+        .insert(ControlsButton)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                "Controls [F1]",
+                TextStyle {
+                    font: asset_server
+                        .load(Path::new("fonts").join("FiraSans-Bold.ttf")),
+                    font_size: 28.0,
+                    color: Color::WHITE,
+                },
+            ));
+        });
+}
+
+pub(crate) fn open_controls(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut state: ResMut<State<AppState>>,
+    mut button_query: Query<
+        (&Interaction, &mut UiColor),
+        ControlsButtonInteraction,
+    >,
+) {
+    let mut open = keyboard_input.just_pressed(KeyCode::F1);
+    for (interaction, mut color) in &mut button_query {
+        match *interaction {
+            Interaction::Clicked => open = true,
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    }
+
+    if open {
+        state.push(AppState::Controls).unwrap();
+    }
+}
+
+pub(crate) fn despawn_controls_button(
+    mut commands: Commands,
+    button_query: Query<Entity, With<ControlsButton>>,
+) {
+    for entity in &button_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct ControlsScreen;
+
+/// A row of the controls screen.
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ControlsRow {
+    Action(GameAction),
+    Defaults,
+    Back,
+}
+
+impl ControlsRow {
+    const ALL: [ControlsRow; 7] = [
+        ControlsRow::Action(GameAction::Flap),
+        ControlsRow::Action(GameAction::Pause),
+        ControlsRow::Action(GameAction::Restart),
+        ControlsRow::Action(GameAction::Menu),
+        ControlsRow::Action(GameAction::ToggleDebug),
+        ControlsRow::Defaults,
+        ControlsRow::Back,
+    ];
+}
+
+/// Where the player is on the controls screen.
+#[derive(Default)]
+pub(crate) struct Rebinding {
+    selected: usize,
+    /// The action waiting for a button to be pressed.
+    listening: Option<GameAction>,
+}
+
+fn row_label(
+    row: ControlsRow,
+    bindings: &InputBindings,
+    rebinding: &Rebinding,
+) -> String {
+    match row {
+        ControlsRow::Action(action) if rebinding.listening == Some(action) => {
+            format!(
+                "{}: press a button\n(Backspace clears, Esc cancels)",
+                action
+            )
+        }
+        ControlsRow::Action(action) => {
+            let buttons: Vec<String> = bindings
+                .buttons(action)
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            format!("{}: {}", action, buttons.join(", "))
+        }
+        ControlsRow::Defaults => "Reset to defaults".to_string(),
+        ControlsRow::Back => "Back".to_string(),
+    }
+}
+
+pub(crate) fn spawn_controls_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    bindings: Res<InputBindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    let font = asset_server.load(Path::new("fonts").join("FiraSans-Bold.ttf"));
+    *rebinding = Rebinding::default();
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.9).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                "Controls",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 80.0,
+                    color: Color::WHITE,
+                },
+            ));
+
+            for (index, row) in ControlsRow::ALL.into_iter().enumerate() {
+                let color = if index == rebinding.selected {
+                    HOVERED_BUTTON
+                } else {
+                    NORMAL_BUTTON
+                };
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(440.0), Val::Px(70.0)),
+                            margin: UiRect::all(Val::Px(6.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        color: color.into(),
+                        ..default()
+                    })
+                    .insert(row)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(
+                            TextBundle::from_section(
+                                row_label(row, &bindings, &rebinding),
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: 24.0,
+                                    color: Color::WHITE,
+                                },
+                            )
+                            .with_text_alignment(TextAlignment::CENTER),
+                        );
+                    });
+            }
+        })
+        .insert(ControlsScreen);
+}
+
+/// Picks a row with the arrow keys and Return or a click, and binds the next
+/// button pressed to the picked action.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn rebind_controls(
+    mut buttons: Buttons,
+    mut bindings: ResMut<InputBindings>,
+    mut rebinding: ResMut<Rebinding>,
+    storage: Res<GameStorage>,
+    mut state: ResMut<State<AppState>>,
+    interaction_query: Query<&Interaction>,
+    mut row_query: Query<(&Interaction, &ControlsRow, &mut UiColor, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    let on_ui = interaction_query
+        .iter()
+        .any(|interaction| *interaction != Interaction::None);
+
+    if let Some(action) = rebinding.listening {
+        // Clicking the screen's own rows doesn't count as a button
+        let pressed = buttons
+            .all_just_pressed()
+            .find(|button| !(button.is_pointer() && on_ui));
+        match pressed {
+            None => return,
+            Some(ActionButton::Key(KeyCode::Escape)) => {}
+            Some(ActionButton::Key(KeyCode::Back | KeyCode::Delete)) => {
+                bindings.clear(action);
+                bindings.save(&storage);
+            }
+            Some(button) => {
+                info!("Binding {} to {}", button, action);
+                bindings.bind(action, button);
+                bindings.save(&storage);
+            }
+        }
+        rebinding.listening = None;
+        if let Some(button) = pressed {
+            // Or it would pick the row again straight away
+            buttons.clear_just_pressed(button);
+        }
+    }
+
+    let rows = ControlsRow::ALL.len();
+    let mut chosen = None;
+    if buttons.just_pressed(ActionButton::Key(KeyCode::Down)) {
+        rebinding.selected = (rebinding.selected + 1) % rows;
+    }
+    if buttons.just_pressed(ActionButton::Key(KeyCode::Up)) {
+        rebinding.selected = (rebinding.selected + rows - 1) % rows;
+    }
+    if buttons.just_pressed(ActionButton::Key(KeyCode::Return)) {
+        chosen = Some(ControlsRow::ALL[rebinding.selected]);
+    }
+    if buttons.just_pressed(ActionButton::Key(KeyCode::Escape)) {
+        chosen = Some(ControlsRow::Back);
+    }
+    // Whatever is below the screen shouldn't read them as well
+    buttons.clear_just_pressed(ActionButton::Key(KeyCode::Return));
+    buttons.clear_just_pressed(ActionButton::Key(KeyCode::Escape));
+    for (interaction, row, _, _) in &row_query {
+        if *interaction == Interaction::Clicked {
+            chosen = Some(*row);
+        }
+    }
+
+    match chosen {
+        Some(ControlsRow::Action(action)) => rebinding.listening = Some(action),
+        Some(ControlsRow::Defaults) => {
+            *bindings = InputBindings::default();
+            bindings.save(&storage);
+        }
+        Some(ControlsRow::Back) => {
+            state.pop().unwrap();
+            return;
+        }
+        None => {}
+    }
+
+    let relabel = bindings.is_changed() || rebinding.is_changed();
+    let selected = ControlsRow::ALL[rebinding.selected];
+    for (interaction, row, mut color, children) in &mut row_query {
+        let highlighted = *row == selected || *interaction != Interaction::None;
+        let wanted = if highlighted {
+            HOVERED_BUTTON
+        } else {
+            NORMAL_BUTTON
+        };
+        if color.0 != wanted {
+            color.0 = wanted;
+        }
+
+        if !relabel {
+            continue;
+        }
+        let label = row_label(*row, &bindings, &rebinding);
+        for &child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(child) {
+                if text.sections[0].value != label {
+                    text.sections[0].value = label.clone();
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn despawn_controls_screen(
+    mut commands: Commands,
+    screen_query: Query<Entity, With<ControlsScreen>>,
+) {
+    for entity in &screen_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,303 @@
+//! Hand-built courses: every pair of pipes laid out in a file, played the
+//! same way on every run. Tutorials, set pieces and the like are built this
+//! way, and once the last pair is out the random generator takes over as in
+//! an endless run.
+//!
+//! Courses are written in RON, one entry per pair in the order they come:
+//!
+//! ```ron
+//! (
+//!     name: "Warm up",
+//!     pairs: [
+//!         (spacing: 0.0, gap_y: 0.0, gap_size: 400.0),
+//!         (spacing: 300.0, gap_y: 120.0, gap_size: 350.0),
+//!         (
+//!             spacing: 350.0,
+//!             gap_y: -80.0,
+//!             gap_size: 320.0,
+//!             variant: Bobbing(phase: 0.0),
+//!         ),
+//!     ],
+//! )
+//! ```
+//!
+//! `spacing` is how far behind the previous pair one comes, or after the
+//! start of the run for the first, in 'pixels'. The pairs scroll by as fast
+//! as the difficulty curve says, so the spacing holds whatever the speed.
+//! `variant` is left out for pairs that stand still.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+use crate::difficulty::Difficulty;
+use crate::obstacle::{spawn_obstacle_pair, Motion, ObstacleStyle};
+use crate::replay::TICK_SECS;
+use crate::{GameMode, OBSTACLE_SPAWN_X};
+
+/// A hand-built run of obstacles.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Course {
+    #[serde(default)]
+    pub name: String,
+    pub pairs: Vec<CoursePair>,
+}
+
+/// One pair of pipes on a course.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "PairFields")]
+pub struct CoursePair {
+    /// How far behind the previous pair this one comes.
+    pub spacing: f32,
+    /// Height of the middle of the opening.
+    pub gap_y: f32,
+    pub gap_size: f32,
+    pub variant: Variant,
+}
+
+/// How a pair on a course moves, as [`Motion`] but without the timing the
+/// game works out on its own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Variant {
+    #[default]
+    Still,
+    /// The whole pair bobs up and down, starting `phase` radians into the
+    /// swing.
+    Bobbing { phase: f32 },
+    /// The gap opens wider and closes again, starting `phase` radians in.
+    Breathing { phase: f32 },
+    /// The top or bottom pipe slides into place just before the bird gets
+    /// there.
+    Sliding { top: bool },
+}
+
+impl Variant {
+    /// How the pair moves if it's spawned `arrival` ticks away from the bird.
+    pub fn motion(self, arrival: u64) -> Motion {
+        match self {
+            Variant::Still => Motion::Still,
+            Variant::Bobbing { phase } => Motion::Bobbing { phase },
+            Variant::Breathing { phase } => Motion::Breathing { phase },
+            Variant::Sliding { top } => Motion::sliding(top, arrival),
+        }
+    }
+}
+
+/// A pair as written in the file, checked before it becomes a
+/// [`CoursePair`].
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PairFields {
+    spacing: f32,
+    gap_y: f32,
+    gap_size: f32,
+    #[serde(default)]
+    variant: Variant,
+}
+
+impl TryFrom<PairFields> for CoursePair {
+    type Error = String;
+
+    fn try_from(fields: PairFields) -> Result<Self, Self::Error> {
+        let phase = match fields.variant {
+            Variant::Bobbing { phase } | Variant::Breathing { phase } => phase,
+            Variant::Still | Variant::Sliding { .. } => 0.0,
+        };
+        for (field, value, valid, expected) in [
+            (
+                "spacing",
+                fields.spacing,
+                fields.spacing >= 0.0,
+                "zero or more",
+            ),
+            ("gap_y", fields.gap_y, true, "a number"),
+            (
+                "gap_size",
+                fields.gap_size,
+                fields.gap_size > 0.0,
+                "more than zero",
+            ),
+            ("phase", phase, true, "a number"),
+        ] {
+            if !(value.is_finite() && valid) {
+                return Err(format!(
+                    "`{}` is {} but must be {}",
+                    field, value, expected
+                ));
+            }
+        }
+
+        Ok(CoursePair {
+            spacing: fields.spacing,
+            gap_y: fields.gap_y,
+            gap_size: fields.gap_size,
+            variant: fields.variant,
+        })
+    }
+}
+
+/// Where and why a course file couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CourseError {
+    /// Counted from 1.
+    pub line: usize,
+    /// Counted from 1, in characters.
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CourseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid course at line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for CourseError {}
+
+impl CourseError {
+    /// The error `err` raised with `remainder` of `text` still unread.
+    fn new(text: &str, remainder: &str, err: ron::Error) -> Self {
+        let ron::error::Position { line, col } = err.position;
+        // Errors raised by serde rather than the parser come without a
+        // position, but the parser stopped right where they happened
+        let (line, column) = if line == 0 {
+            let read = &text[..text.len() - remainder.len()];
+            let line_start = read.rfind('\n').map_or(0, |at| at + 1);
+            (
+                read.matches('\n').count() + 1,
+                read[line_start..].chars().count() + 1,
+            )
+        } else {
+            (line, col)
+        };
+
+        CourseError {
+            line,
+            column,
+            message: err.code.to_string(),
+        }
+    }
+}
+
+impl Course {
+    /// Parses and checks a course written in RON.
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, CourseError> {
+        let text = String::from_utf8_lossy(bytes);
+        let mut deserializer = ron::Deserializer::from_str(&text)
+            .map_err(|err| CourseError::new(&text, &text, err))?;
+        let parsed = Course::deserialize(&mut deserializer)
+            .and_then(|course| deserializer.end().map(|_| course));
+        parsed.map_err(|err| {
+            CourseError::new(&text, &deserializer.remainder(), err)
+        })
+    }
+
+    /// Reads a course file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        Course::from_ron(&bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Plays `course` on every run, before the random generator takes over.
+pub fn play_course(app: &mut App, course: Course) {
+    info!(
+        "Playing course {:?}, {} pairs long",
+        course.name,
+        course.pairs.len()
+    );
+    app.insert_resource(GameMode::Course)
+        .insert_resource(CoursePlayback::new(course));
+}
+
+/// The course being played and how far into it the run is.
+#[derive(Clone)]
+pub(crate) struct CoursePlayback {
+    course: Course,
+    /// How far the course has scrolled when a run starts, which brings its
+    /// first pair in closer than the spawn point.
+    head_start: f32,
+    /// The next pair to spawn.
+    next: usize,
+    /// How far the obstacles have scrolled since the last pair was spawned.
+    scrolled: f32,
+}
+
+impl CoursePlayback {
+    fn new(course: Course) -> Self {
+        CoursePlayback::starting_at(course, 0.0)
+    }
+
+    /// Plays `course` as if it had already scrolled `head_start` when the
+    /// run starts.
+    pub(crate) fn starting_at(course: Course, head_start: f32) -> Self {
+        CoursePlayback {
+            course,
+            head_start,
+            next: 0,
+            scrolled: head_start,
+        }
+    }
+
+    /// Whether every pair of the course is out.
+    pub(crate) fn finished(&self) -> bool {
+        self.next >= self.course.pairs.len()
+    }
+}
+
+pub(crate) fn start_course(playback: Option<ResMut<CoursePlayback>>) {
+    if let Some(mut playback) = playback {
+        playback.next = 0;
+        playback.scrolled = playback.head_start;
+    }
+}
+
+/// Spawns the pairs of the course as their turn comes.
+pub(crate) fn spawn_course_obstacles(
+    mut commands: Commands,
+    playback: Option<ResMut<CoursePlayback>>,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    difficulty: Res<Difficulty>,
+) {
+    let mut playback = match playback {
+        Some(playback) => playback,
+        None => return,
+    };
+
+    while let Some(&pair) = playback.course.pairs.get(playback.next) {
+        if playback.scrolled < pair.spacing {
+            break;
+        }
+        // Whatever it's late by, it's that much further along already
+        playback.scrolled -= pair.spacing;
+        playback.next += 1;
+        let x = OBSTACLE_SPAWN_X - playback.scrolled;
+
+        let mut style = ObstacleStyle::new(&asset_server, &config, &difficulty);
+        // The bird flies at x = 0
+        let arrival = x / -difficulty.scroll_speed / TICK_SECS;
+        style.motion = pair.variant.motion(arrival as u64);
+        spawn_obstacle_pair(
+            &mut commands,
+            x,
+            pair.gap_y,
+            pair.gap_size,
+            &style,
+        );
+    }
+
+    // Every pipe moves together, at the speed the difficulty gives them
+    playback.scrolled += -difficulty.scroll_speed * TICK_SECS;
+}
@@ -0,0 +1,329 @@
+//! The daily challenge: one course per UTC day, the same for everyone, with a
+//! single scored attempt. Any run after that is practice.
+
+use std::path::Path;
+
+use bevy::prelude::*;
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::seed::GameSeed;
+use crate::storage::GameStorage;
+use crate::{GameMode, Score, HOVERED_BUTTON, NORMAL_BUTTON};
+
+const STORAGE_KEY: &str = "daily_scores";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyEntry {
+    pub date: NaiveDate,
+    pub score: u128,
+}
+
+/// Daily challenge results, kept apart from the endless high scores.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DailyScores {
+    /// The scored attempt for the most recent day played.
+    pub latest: Option<DailyEntry>,
+    /// The best scored attempt of any day.
+    pub best: Option<DailyEntry>,
+}
+
+impl DailyScores {
+    /// Whether the scored attempt for `date` has been used up.
+    pub fn attempted(&self, date: NaiveDate) -> bool {
+        self.latest.is_some_and(|latest| latest.date == date)
+    }
+
+    fn record(&mut self, entry: DailyEntry) {
+        self.latest = Some(entry);
+        if self.best.is_none_or(|best| entry.score > best.score) {
+            self.best = Some(entry);
+        }
+    }
+
+    fn load(storage: &GameStorage) -> Self {
+        storage
+            .load(STORAGE_KEY)
+            .and_then(|saved| match ron::from_str(&saved) {
+                Ok(scores) => Some(scores),
+                Err(err) => {
+                    warn!("Discarding unreadable daily scores: {}", err);
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    fn save(&self, storage: &GameStorage) {
+        let result = ron::to_string(self)
+            .map_err(|err| err.to_string())
+            .and_then(|saved| {
+                storage
+                    .save(STORAGE_KEY, &saved)
+                    .map_err(|err| err.to_string())
+            });
+
+        if let Err(err) = result {
+            warn!("Unable to save daily scores: {}", err);
+        }
+    }
+}
+
+/// The seed everyone plays on `date`.
+pub fn daily_seed(date: NaiveDate) -> u64 {
+    date.num_days_from_ce() as u64
+}
+
+fn today() -> NaiveDate {
+    Utc::now().date_naive()
+}
+
+/// Whether the daily run being played counts towards the daily scores.
+pub(crate) struct DailyAttempt {
+    date: NaiveDate,
+    practice: bool,
+}
+
+/// The endless seed, put aside while the daily course is selected.
+#[derive(Default)]
+pub(crate) struct StashedSeed(Option<GameSeed>);
+
+#[derive(Component)]
+pub(crate) struct ModeButton;
+
+#[derive(Component)]
+pub(crate) struct DailyRunText;
+
+type ModeButtonInteraction = (Changed<Interaction>, With<ModeButton>);
+
+pub(crate) fn load_daily_scores(
+    mut daily_scores: ResMut<DailyScores>,
+    storage: Res<GameStorage>,
+) {
+    *daily_scores = DailyScores::load(&storage);
+}
+
+fn mode_label(mode: GameMode, daily_scores: &DailyScores) -> String {
+    match mode {
+        GameMode::Endless => "Mode: Endless [Tab]".to_string(),
+        GameMode::Daily => {
+            let status = match daily_scores.latest {
+                Some(latest) if latest.date == today() => {
+                    format!("Played today: {} (practice only)", latest.score)
+                }
+                _ => "Not played today".to_string(),
+            };
+            let best = daily_scores
+                .best
+                .map_or(String::new(), |best| format!(", best {}", best.score));
+            format!("Mode: Daily [Tab]\n{}{}", status, best)
+        }
+        GameMode::Replay => "Mode: Replay".to_string(),
+        GameMode::Demo => "Mode: Demo".to_string(),
+        GameMode::Online => "Mode: Online".to_string(),
+        GameMode::Course => "Mode: Course".to_string(),
+        GameMode::Versus => {
+            "Mode: Versus [Tab]\nTwo players, last bird flying wins".to_string()
+        }
+    }
+}
+
+pub(crate) fn spawn_mode_button(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mode: Res<GameMode>,
+    daily_scores: Res<DailyScores>,
+) {
+    commands
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(120.0),
+                    left: Val::Px(50.0),
+                    ..default()
+                },
+                size: Size::new(Val::Px(400.0), Val::Px(90.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: NORMAL_BUTTON.into(),
+            ..default()
+        })
+        .insert(ModeButton)
+        .with_children(|parent| {
+            parent.spawn_bundle(
+                TextBundle::from_section(
+                    mode_label(*mode, &daily_scores),
+                    TextStyle {
+                        font: asset_server
+                            .load(Path::new("fonts").join("FiraSans-Bold.ttf")),
+                        font_size: 28.0,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_text_alignment(TextAlignment::CENTER),
+            );
+        });
+}
+
+/// Cycles through endless, daily and versus with Tab or a click on the mode
+/// button.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn select_mode(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut mode: ResMut<GameMode>,
+    mut seed: ResMut<GameSeed>,
+    mut stashed_seed: ResMut<StashedSeed>,
+    daily_scores: Res<DailyScores>,
+    mut button_query: Query<
+        (&Interaction, &mut UiColor),
+        ModeButtonInteraction,
+    >,
+    children_query: Query<&Children, With<ModeButton>>,
+    mut text_query: Query<&mut Text>,
+) {
+    let mut toggle = keyboard_input.just_pressed(KeyCode::Tab);
+    for (interaction, mut color) in &mut button_query {
+        match *interaction {
+            Interaction::Clicked => toggle = true,
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    }
+
+    // A replay brings its own seed, online runs get theirs from the relay and
+    // a course is picked when the game starts, so there's nothing to switch to
+    if !toggle
+        || matches!(
+            *mode,
+            GameMode::Replay | GameMode::Online | GameMode::Course
+        )
+    {
+        return;
+    }
+
+    *mode = match *mode {
+        GameMode::Endless => {
+            let daily = GameSeed::fixed(daily_seed(today()));
+            stashed_seed.0 = Some(std::mem::replace(&mut *seed, daily));
+            GameMode::Daily
+        }
+        GameMode::Daily => {
+            *seed = stashed_seed.0.take().unwrap_or_default();
+            GameMode::Versus
+        }
+        GameMode::Versus => GameMode::Endless,
+        GameMode::Replay
+        | GameMode::Online
+        | GameMode::Demo
+        | GameMode::Course => *mode,
+    };
+    info!("Game mode is now {:?}", *mode);
+
+    for children in &children_query {
+        for &child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(child) {
+                text.sections[0].value = mode_label(*mode, &daily_scores);
+            }
+        }
+    }
+}
+
+pub(crate) fn despawn_mode_button(
+    mut commands: Commands,
+    button_query: Query<Entity, With<ModeButton>>,
+) {
+    for entity in &button_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Uses up the day's scored attempt as soon as the run starts, so quitting
+/// early doesn't give a second try.
+pub(crate) fn start_daily_run(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mode: Res<GameMode>,
+    mut seed: ResMut<GameSeed>,
+    mut daily_scores: ResMut<DailyScores>,
+    storage: Res<GameStorage>,
+) {
+    if *mode != GameMode::Daily {
+        return;
+    }
+
+    let date = today();
+    // The day may have rolled over since the mode was picked
+    if seed.seed() != daily_seed(date) {
+        *seed = GameSeed::fixed(daily_seed(date));
+    }
+
+    let practice = daily_scores.attempted(date);
+    if !practice {
+        daily_scores.latest = Some(DailyEntry { date, score: 0 });
+        daily_scores.save(&storage);
+    }
+    commands.insert_resource(DailyAttempt { date, practice });
+
+    let (label, color) = if practice {
+        ("PRACTICE - not scored", Color::ORANGE)
+    } else {
+        ("Daily challenge", Color::GOLD)
+    };
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                format!("{}\n{}", label, date),
+                TextStyle {
+                    font: asset_server
+                        .load(Path::new("fonts").join("FiraSans-Bold.ttf")),
+                    font_size: 30.0,
+                    color,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(10.0),
+                    right: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(DailyRunText);
+}
+
+pub(crate) fn record_daily_score(
+    mode: Res<GameMode>,
+    attempt: Option<Res<DailyAttempt>>,
+    score: Res<Score>,
+    mut daily_scores: ResMut<DailyScores>,
+    storage: Res<GameStorage>,
+) {
+    let attempt = match attempt {
+        Some(attempt) if *mode == GameMode::Daily && !attempt.practice => {
+            attempt
+        }
+        _ => return,
+    };
+
+    info!("Daily challenge for {} scored {}", attempt.date, score.0);
+    daily_scores.record(DailyEntry {
+        date: attempt.date,
+        score: score.0,
+    });
+    daily_scores.save(&storage);
+}
+
+pub(crate) fn end_daily_run(
+    mut commands: Commands,
+    text_query: Query<Entity, With<DailyRunText>>,
+) {
+    commands.remove_resource::<DailyAttempt>();
+    for entity in &text_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
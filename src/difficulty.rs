@@ -0,0 +1,157 @@
+//! How much harder the game gets the further a run goes.
+//!
+//! A [`DifficultyCurve`] decides how quickly a run moves from the easiest
+//! settings in the [`GameConfig`] to the hardest ones. Whatever the curve,
+//! the gap between two obstacles never closes past what the player can fly
+//! through.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+use crate::highscore::RunClock;
+use crate::obstacle::ObstacleWeights;
+use crate::{Obstacle, Score, SpawnNextObstacle};
+
+/// Maps how far a run has gone to how hard it should be.
+pub trait DifficultyCurve: Send + Sync + 'static {
+    /// How far along towards the hardest settings a run is, from 0 to 1.
+    fn ramp(&self, progress: f32) -> f32;
+}
+
+/// The built-in curves, selectable from the game config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DifficultyPreset {
+    /// Ramps up evenly, reaching the hardest settings at `full_at`.
+    Linear { full_at: f32 },
+    /// Ramps up in `steps` equal jumps, reaching the hardest settings at
+    /// `full_at`.
+    Stepped { full_at: f32, steps: u32 },
+    /// Ramps up quickly at first and slower later, reaching the hardest
+    /// settings at `full_at`.
+    Logarithmic { full_at: f32 },
+    /// Ramps up evenly, but stops `cap` of the way to the hardest settings.
+    Capped { full_at: f32, cap: f32 },
+}
+
+impl DifficultyCurve for DifficultyPreset {
+    fn ramp(&self, progress: f32) -> f32 {
+        let ramp = match *self {
+            DifficultyPreset::Linear { full_at } => progress / full_at,
+            DifficultyPreset::Stepped { full_at, steps } => {
+                let steps = steps.max(1) as f32;
+                (progress / full_at * steps).floor() / steps
+            }
+            DifficultyPreset::Logarithmic { full_at } => {
+                progress.ln_1p() / full_at.ln_1p()
+            }
+            DifficultyPreset::Capped { full_at, cap } => {
+                (progress / full_at).min(cap)
+            }
+        };
+
+        ramp.clamp(0.0, 1.0)
+    }
+}
+
+/// What a curve measures progress in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Progress {
+    Score,
+    ElapsedSecs,
+}
+
+/// Replaces the curve from the game config, for modes that bring their own.
+pub struct CustomDifficultyCurve(pub Box<dyn DifficultyCurve>);
+
+/// The settings the current run is played at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Difficulty {
+    pub gap_size: f32,
+    pub scroll_speed: f32,
+    pub spawn_interval_secs: f32,
+    /// How likely each kind of moving obstacle is to turn up next.
+    pub obstacle_weights: ObstacleWeights,
+}
+
+impl Difficulty {
+    pub fn at(
+        curve: &dyn DifficultyCurve,
+        progress: f32,
+        config: &GameConfig,
+    ) -> Self {
+        let ramp = curve.ramp(progress).clamp(0.0, 1.0);
+        let lerp =
+            |easiest: f32, hardest: f32| easiest + (hardest - easiest) * ramp;
+
+        Difficulty {
+            gap_size: lerp(config.gap_size, config.hardest_gap_size)
+                .max(config.min_gap_size()),
+            scroll_speed: lerp(
+                config.scroll_speed,
+                config.hardest_scroll_speed,
+            ),
+            spawn_interval_secs: lerp(
+                config.spawn_interval_secs,
+                config.hardest_spawn_interval_secs,
+            ),
+            obstacle_weights: config
+                .obstacle_weights
+                .lerp(&config.hardest_obstacle_weights, ramp),
+        }
+    }
+}
+
+impl FromWorld for Difficulty {
+    fn from_world(world: &mut World) -> Self {
+        let config = world.get_resource_or_insert_with(GameConfig::default);
+        Difficulty::at(&config.difficulty, 0.0, &config)
+    }
+}
+
+pub(crate) fn update_difficulty(
+    config: Res<GameConfig>,
+    custom_curve: Option<Res<CustomDifficultyCurve>>,
+    score: Res<Score>,
+    clock: Res<RunClock>,
+    mut difficulty: ResMut<Difficulty>,
+) {
+    let progress = match config.difficulty_progress {
+        Progress::Score => score.0 as f32,
+        Progress::ElapsedSecs => clock.0.elapsed_secs(),
+    };
+    let curve = match &custom_curve {
+        Some(custom_curve) => custom_curve.0.as_ref(),
+        None => &config.difficulty,
+    };
+
+    let next = Difficulty::at(curve, progress, &config);
+    // Only touch the resource when something changed, so the obstacles
+    // aren't updated every tick
+    if *difficulty != next {
+        *difficulty = next;
+    }
+}
+
+pub(crate) fn apply_difficulty(
+    difficulty: Res<Difficulty>,
+    mut timer: ResMut<SpawnNextObstacle>,
+    mut obstacle_query: Query<&mut Velocity, With<Obstacle>>,
+) {
+    if !difficulty.is_changed() {
+        return;
+    }
+
+    debug!("Difficulty is now {:?}", *difficulty);
+    // Every obstacle moves together, so pairs never catch up to each other
+    for mut velocity in &mut obstacle_query {
+        velocity.linvel.x = difficulty.scroll_speed;
+    }
+
+    timer
+        .event_timer
+        .set_duration(Duration::from_secs_f32(difficulty.spawn_interval_secs));
+}
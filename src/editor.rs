@@ -0,0 +1,643 @@
+//! Building courses by hand, in the game itself.
+//!
+//! The editor lays a [`Course`] out the way a run meets it, with the bird
+//! starting at x = 0 and the pipes built by the same code the game spawns
+//! them with, so what's built is exactly what's flown. No pair comes before
+//! the spawn point, where a run's first pair scrolls in from.
+//!
+//! Pan with A and D or the arrow keys. Click to place a pair or drag one
+//! around, scroll or press + and - to size the selected gap, V to change how
+//! it moves and Delete to remove it. S saves the course file, and T or the
+//! button tests the course with the bird dropped where the camera is.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+
+use crate::config::GameConfig;
+use crate::controls::{ActionButton, Buttons, GameAction, InputBindings};
+use crate::course::{Course, CoursePair, CoursePlayback, Variant};
+use crate::difficulty::Difficulty;
+use crate::obstacle::{spawn_obstacle_pair, ObstacleStyle};
+use crate::{
+    ActionKey, AppState, GameMode, WelcomeText, HOVERED_BUTTON, NORMAL_BUTTON,
+    OBSTACLE_SPAWN_X,
+};
+
+/// Where the course is saved unless `--edit` picks another file.
+pub const DEFAULT_COURSE_PATH: &str = "course.course.ron";
+
+/// How fast the camera pans, in 'pixels/second'.
+const PAN_SPEED: f32 = 600.0;
+/// How much a gap grows or shrinks with each step.
+const GAP_STEP: f32 = 10.0;
+/// Drawn over the gap of the selected pair.
+const SELECTED_TINT: Color = Color::rgba(1.0, 0.85, 0.0, 0.3);
+
+const HELP: &str = "Click to place a pair, drag to move it\n\
+                    Scroll or +/- to size its gap, V to change how it \
+                    moves, Delete to remove it\n\
+                    A/D to pan, S to save, T to test, Esc for the menu";
+
+/// The course file the editor opens and saves.
+pub struct CourseFile(pub PathBuf);
+
+impl Default for CourseFile {
+    fn default() -> Self {
+        CourseFile(DEFAULT_COURSE_PATH.into())
+    }
+}
+
+/// A pair as laid out in the editor, `x` from where a run starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct EditedPair {
+    x: f32,
+    gap_y: f32,
+    gap_size: f32,
+    variant: Variant,
+}
+
+/// The course being built.
+#[derive(Default)]
+pub(crate) struct EditedCourse {
+    name: String,
+    pairs: Vec<EditedPair>,
+    selected: Option<usize>,
+    /// Where the selected pair's gap is from the cursor while it's dragged.
+    grab: Option<Vec2>,
+    /// How the last save went.
+    status: String,
+}
+
+impl EditedCourse {
+    fn from_course(course: Course) -> Self {
+        let mut x = OBSTACLE_SPAWN_X;
+        let pairs = course
+            .pairs
+            .iter()
+            .map(|pair| {
+                x += pair.spacing;
+                EditedPair {
+                    x,
+                    gap_y: pair.gap_y,
+                    gap_size: pair.gap_size,
+                    variant: pair.variant,
+                }
+            })
+            .collect();
+
+        EditedCourse {
+            name: course.name,
+            pairs,
+            ..default()
+        }
+    }
+
+    /// The pairs from `start` on as a course, and how far it has to have
+    /// scrolled when a run starts for them to be where they're laid out.
+    fn course_from(&self, start: f32) -> (Course, f32) {
+        let mut ahead: Vec<EditedPair> = self
+            .pairs
+            .iter()
+            .filter(|pair| pair.x >= start)
+            .copied()
+            .collect();
+        ahead.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+        let spawn_x = start + OBSTACLE_SPAWN_X;
+        let head_start = ahead
+            .first()
+            .map_or(0.0, |first| (spawn_x - first.x).max(0.0));
+        let mut last_x = spawn_x - head_start;
+        let pairs = ahead
+            .iter()
+            .map(|pair| {
+                let spacing = pair.x - last_x;
+                last_x = pair.x;
+                CoursePair {
+                    spacing,
+                    gap_y: pair.gap_y,
+                    gap_size: pair.gap_size,
+                    variant: pair.variant,
+                }
+            })
+            .collect();
+
+        let course = Course {
+            name: self.name.clone(),
+            pairs,
+        };
+        (course, head_start)
+    }
+
+    /// The pair drawn at `point`, if any.
+    fn pair_at(&self, point: Vec2, half_width: f32) -> Option<usize> {
+        self.pairs
+            .iter()
+            .enumerate()
+            .map(|(index, pair)| (index, (pair.x - point.x).abs()))
+            .filter(|&(_, distance)| distance <= half_width)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+    }
+}
+
+/// Opens the course at `path` in the editor, and saves it back there. A file
+/// that doesn't exist yet starts out empty.
+pub fn edit_course(app: &mut App, path: impl Into<PathBuf>) -> io::Result<()> {
+    let path = path.into();
+    let edited = if path.exists() {
+        EditedCourse::from_course(Course::load(&path)?)
+    } else {
+        EditedCourse::default()
+    };
+
+    app.insert_resource(CourseFile(path))
+        .insert_resource(edited);
+    Ok(())
+}
+
+/// What to go back to once a test run is over.
+pub(crate) struct EditorTest {
+    mode: GameMode,
+    playback: Option<CoursePlayback>,
+}
+
+#[derive(Component)]
+pub(crate) struct EditorButton;
+
+#[derive(Component)]
+pub(crate) struct TestButton;
+
+/// Anything that only shows while editing.
+#[derive(Component)]
+pub(crate) struct EditorEntity;
+
+/// The pipes and highlight drawn for the course, redrawn on every edit.
+#[derive(Component)]
+pub(crate) struct CourseDrawing;
+
+#[derive(Component)]
+pub(crate) struct EditorText;
+
+type EditorButtonInteraction = (Changed<Interaction>, With<EditorButton>);
+type TestButtonInteraction = (Changed<Interaction>, With<TestButton>);
+
+fn button_bundle(bottom: f32, left: f32) -> ButtonBundle {
+    ButtonBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                bottom: Val::Px(bottom),
+                left: Val::Px(left),
+                ..default()
+            },
+            size: Size::new(Val::Px(400.0), Val::Px(70.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        color: NORMAL_BUTTON.into(),
+        ..default()
+    }
+}
+
+fn button_text(asset_server: &AssetServer, label: &str) -> TextBundle {
+    TextBundle::from_section(
+        label,
+        TextStyle {
+            font: asset_server
+                .load(Path::new("fonts").join("FiraSans-Bold.ttf")),
+            font_size: 28.0,
+            color: Color::WHITE,
+        },
+    )
+}
+
+/// Opens the editor from the menu with F2 or a click.
+pub(crate) fn spawn_editor_button(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands
+        .spawn_bundle(button_bundle(230.0, 50.0))
+        .insert(EditorButton)
+        .with_children(|parent| {
+            parent.spawn_bundle(button_text(&asset_server, "Editor [F2]"));
+        });
+}
+
+/// Opens the editor when asked to, or straight away when a test run has
+/// just gone back to the menu.
+pub(crate) fn open_editor(
+    mut commands: Commands,
+    mut buttons: Buttons,
+    bindings: Res<InputBindings>,
+    test: Option<Res<EditorTest>>,
+    mut mode: ResMut<GameMode>,
+    mut state: ResMut<State<AppState>>,
+    mut button_query: Query<
+        (&Interaction, &mut UiColor),
+        EditorButtonInteraction,
+    >,
+) {
+    let mut open = buttons.just_pressed(ActionButton::Key(KeyCode::F2));
+    for (interaction, mut color) in &mut button_query {
+        match *interaction {
+            Interaction::Clicked => open = true,
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    }
+
+    if let Some(test) = test {
+        commands.remove_resource::<EditorTest>();
+        *mode = test.mode;
+        match &test.playback {
+            Some(playback) => commands.insert_resource(playback.clone()),
+            None => commands.remove_resource::<CoursePlayback>(),
+        }
+        open = true;
+
+        // Stop the editor from reading the press that left the run as
+        // leaving it too
+        for &button in bindings.buttons(GameAction::Menu) {
+            buttons.clear_just_pressed(button);
+        }
+    }
+
+    if open {
+        state.set(AppState::Editor).unwrap();
+    }
+}
+
+pub(crate) fn despawn_editor_button(
+    mut commands: Commands,
+    button_query: Query<Entity, With<EditorButton>>,
+) {
+    for entity in &button_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn label(edited: &EditedCourse, file: &CourseFile) -> String {
+    let selected = match edited.selected.map(|index| edited.pairs[index]) {
+        Some(pair) => format!(
+            "Selected: gap of {:.0} at {:.0}, {:?}",
+            pair.gap_size, pair.gap_y, pair.variant
+        ),
+        None => "Nothing selected".to_string(),
+    };
+    format!(
+        "{} ({} pairs)\n{}\n{}\n{}",
+        file.0.display(),
+        edited.pairs.len(),
+        selected,
+        HELP,
+        edited.status
+    )
+}
+
+pub(crate) fn spawn_editor(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    file: Res<CourseFile>,
+    mut edited: ResMut<EditedCourse>,
+    text_query: Query<Entity, With<WelcomeText>>,
+) {
+    for entity in &text_query {
+        commands.entity(entity).despawn();
+    }
+
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                label(&edited, &file),
+                TextStyle {
+                    font: asset_server
+                        .load(Path::new("fonts").join("FiraSans-Bold.ttf")),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(EditorText)
+        .insert(EditorEntity);
+    commands
+        .spawn_bundle(button_bundle(30.0, 50.0))
+        .insert(TestButton)
+        .insert(EditorEntity)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(button_text(&asset_server, "Test from here [T]"));
+        });
+
+    // Draws what was left from last time
+    edited.set_changed();
+}
+
+pub(crate) fn pan_camera(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+) {
+    let direction: f32 = [
+        (KeyCode::A, -1.0),
+        (KeyCode::Left, -1.0),
+        (KeyCode::D, 1.0),
+        (KeyCode::Right, 1.0),
+    ]
+    .iter()
+    .filter(|(key_code, _)| keyboard_input.pressed(*key_code))
+    .map(|(_, direction)| direction)
+    .sum();
+
+    for mut camera in &mut camera_query {
+        camera.translation.x +=
+            direction.clamp(-1.0, 1.0) * PAN_SPEED * time.delta_seconds();
+    }
+}
+
+/// Where the cursor points in the world, if it's over the window.
+fn cursor_in_world(windows: &Windows, camera: &Transform) -> Option<Vec2> {
+    let window = windows.get_primary()?;
+    let cursor = window.cursor_position()?;
+    let size = Vec2::new(window.width(), window.height());
+    Some(camera.translation.truncate() + cursor - size / 2.0)
+}
+
+fn next_variant(variant: Variant) -> Variant {
+    match variant {
+        Variant::Still => Variant::Bobbing { phase: 0.0 },
+        Variant::Bobbing { .. } => Variant::Breathing { phase: 0.0 },
+        Variant::Breathing { .. } => Variant::Sliding { top: true },
+        Variant::Sliding { top: true } => Variant::Sliding { top: false },
+        Variant::Sliding { top: false } => Variant::Still,
+    }
+}
+
+/// Places, drags, resizes and removes pairs. The course is only touched when
+/// something actually changes, since that redraws it.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn edit_pairs(
+    windows: Res<Windows>,
+    mouse_input: Res<Input<MouseButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut wheel_evr: EventReader<MouseWheel>,
+    config: Res<GameConfig>,
+    camera_query: Query<&Transform, With<Camera2d>>,
+    interaction_query: Query<&Interaction>,
+    mut edited: ResMut<EditedCourse>,
+) {
+    let cursor = camera_query
+        .get_single()
+        .ok()
+        .and_then(|camera| cursor_in_world(&windows, camera));
+    // Clicking the editor's own button doesn't place a pair
+    let on_ui = interaction_query
+        .iter()
+        .any(|interaction| *interaction != Interaction::None);
+
+    if let Some(cursor) = cursor {
+        if mouse_input.just_pressed(MouseButton::Left) && !on_ui {
+            let index = match edited.pair_at(cursor, config.obstacle_width) {
+                Some(index) => index,
+                None => {
+                    edited.pairs.push(EditedPair {
+                        x: cursor.x.max(OBSTACLE_SPAWN_X),
+                        gap_y: cursor.y,
+                        gap_size: config.gap_size,
+                        variant: Variant::Still,
+                    });
+                    edited.pairs.len() - 1
+                }
+            };
+            let pair = edited.pairs[index];
+            edited.selected = Some(index);
+            edited.grab = Some(Vec2::new(pair.x, pair.gap_y) - cursor);
+        } else if let (Some(index), Some(grab)) = (edited.selected, edited.grab)
+        {
+            let moved = cursor + grab;
+            let (x, gap_y) = (moved.x.max(OBSTACLE_SPAWN_X), moved.y);
+            let pair = edited.pairs[index];
+            if (pair.x, pair.gap_y) != (x, gap_y) {
+                let pair = &mut edited.pairs[index];
+                pair.x = x;
+                pair.gap_y = gap_y;
+            }
+        }
+    }
+    if mouse_input.just_released(MouseButton::Left) && edited.grab.is_some() {
+        edited.grab = None;
+    }
+
+    let mut steps: f32 = wheel_evr.iter().map(|wheel| wheel.y.signum()).sum();
+    if keyboard_input.any_just_pressed([KeyCode::Equals, KeyCode::NumpadAdd]) {
+        steps += 1.0;
+    }
+    if keyboard_input
+        .any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract])
+    {
+        steps -= 1.0;
+    }
+
+    let index = match edited.selected {
+        Some(index) => index,
+        None => return,
+    };
+    if steps != 0.0 {
+        let pair = &mut edited.pairs[index];
+        pair.gap_size =
+            (pair.gap_size + steps * GAP_STEP).max(config.min_gap_size());
+    }
+    if keyboard_input.just_pressed(KeyCode::V) {
+        let pair = &mut edited.pairs[index];
+        pair.variant = next_variant(pair.variant);
+    }
+    if keyboard_input.any_just_pressed([KeyCode::Delete, KeyCode::Back]) {
+        edited.pairs.remove(index);
+        edited.selected = None;
+        edited.grab = None;
+    }
+}
+
+/// Redraws the course whenever it's edited, with the game's own pipes.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_course(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    difficulty: Res<Difficulty>,
+    file: Res<CourseFile>,
+    edited: Res<EditedCourse>,
+    drawing_query: Query<Entity, With<CourseDrawing>>,
+    mut text_query: Query<&mut Text, With<EditorText>>,
+) {
+    if !edited.is_changed() {
+        return;
+    }
+
+    for entity in &drawing_query {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let mut style = ObstacleStyle::new(&asset_server, &config, &difficulty);
+    // Laid out where they'll be met, not scrolling
+    style.speed = 0.0;
+    for (index, pair) in edited.pairs.iter().enumerate() {
+        let (top, bottom) = spawn_obstacle_pair(
+            &mut commands,
+            pair.x,
+            pair.gap_y,
+            pair.gap_size,
+            &style,
+        );
+        for entity in [top, bottom] {
+            commands
+                .entity(entity)
+                .insert(CourseDrawing)
+                .insert(EditorEntity);
+        }
+
+        if edited.selected == Some(index) {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: SELECTED_TINT,
+                        custom_size: Some(Vec2::new(
+                            style.half_width * 2.0,
+                            pair.gap_size,
+                        )),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(pair.x, pair.gap_y, 1.0),
+                    ..default()
+                })
+                .insert(CourseDrawing)
+                .insert(EditorEntity);
+        }
+    }
+
+    for mut text in &mut text_query {
+        text.sections[0].value = label(&edited, &file);
+    }
+}
+
+pub(crate) fn save_course(
+    keyboard_input: Res<Input<KeyCode>>,
+    file: Res<CourseFile>,
+    mut edited: ResMut<EditedCourse>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::S) {
+        return;
+    }
+
+    let (course, _) = edited.course_from(0.0);
+    let result = ron::ser::to_string_pretty(&course, PrettyConfig::default())
+        .map_err(|err| err.to_string())
+        .and_then(|saved| {
+            fs::write(&file.0, saved).map_err(|err| err.to_string())
+        });
+
+    edited.status = match result {
+        Ok(()) => {
+            info!("Saved the course to {}", file.0.display());
+            format!("Saved to {}", file.0.display())
+        }
+        Err(err) => {
+            warn!("Unable to save the course: {}", err);
+            format!("Unable to save: {}", err)
+        }
+    };
+}
+
+/// Starts a run on the course with the bird where the camera is, with T or
+/// the button.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn test_from_here(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    edited: Res<EditedCourse>,
+    playback: Option<Res<CoursePlayback>>,
+    action_key: Option<Res<ActionKey>>,
+    mut mode: ResMut<GameMode>,
+    mut state: ResMut<State<AppState>>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+    mut button_query: Query<
+        (&Interaction, &mut UiColor),
+        TestButtonInteraction,
+    >,
+) {
+    let mut test = keyboard_input.just_pressed(KeyCode::T);
+    for (interaction, mut color) in &mut button_query {
+        match *interaction {
+            Interaction::Clicked => test = true,
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    }
+    if !test {
+        return;
+    }
+
+    let mut camera = match camera_query.get_single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let (course, head_start) = edited.course_from(camera.translation.x);
+    info!(
+        "Testing the course from {}, {} pairs ahead",
+        camera.translation.x,
+        course.pairs.len()
+    );
+    commands.insert_resource(EditorTest {
+        mode: *mode,
+        playback: playback.map(|playback| playback.clone()),
+    });
+    commands.insert_resource(CoursePlayback::starting_at(course, head_start));
+    *mode = GameMode::Course;
+    // Runs are laid out around the bird at x = 0, so the course is shifted
+    // there rather than the bird
+    camera.translation.x = 0.0;
+    if action_key.is_none() {
+        // Nobody picked one on the way in
+        commands.insert_resource(ActionKey(ActionButton::Key(KeyCode::Space)));
+    }
+    state.set(AppState::InGame).unwrap();
+}
+
+pub(crate) fn leave_editor(
+    buttons: Buttons,
+    bindings: Res<InputBindings>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if bindings.just_pressed(GameAction::Menu, &buttons) {
+        state.set(AppState::Menu).unwrap();
+    }
+}
+
+pub(crate) fn despawn_editor(
+    mut commands: Commands,
+    editor_query: Query<Entity, With<EditorEntity>>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+) {
+    for entity in &editor_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    for mut camera in &mut camera_query {
+        camera.translation.x = 0.0;
+    }
+}
@@ -0,0 +1,216 @@
+//! The game as a reinforcement learning environment, stepped one tick at a
+//! time on the [`headless_app`].
+//!
+//! ```no_run
+//! use bevy_bird::env::{Action, Env};
+//!
+//! let mut env = Env::new();
+//! let mut observation = env.reset(42);
+//! loop {
+//!     let action = match observation.obstacles[0] {
+//!         Some(next) if observation.bird_y < next.gap_y => Action::Flap,
+//!         _ => Action::Noop,
+//!     };
+//!     let (next, _reward, done) = env.step(action);
+//!     if done {
+//!         break;
+//!     }
+//!     observation = next;
+//! }
+//! ```
+
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Serialize;
+
+use crate::config::GameConfig;
+use crate::controls::ActionButton;
+use crate::obstacle::PairMotion;
+use crate::seed::GameSeed;
+use crate::{
+    headless_app, ActionKey, AppState, FlapRequest, Gap, Player, Score,
+};
+
+/// Reward for every tick the bird stays alive.
+pub const ALIVE_REWARD: f32 = 0.01;
+/// Reward for every obstacle passed.
+pub const SCORE_REWARD: f32 = 1.0;
+/// Reward for the tick the bird dies on.
+pub const DEATH_REWARD: f32 = -1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Flap,
+    Noop,
+}
+
+/// A pair of obstacles still ahead of the bird.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct NextObstacle {
+    /// How far to the right of the bird the middle of the pair is.
+    pub distance: f32,
+    /// Height of the middle of the opening.
+    pub gap_y: f32,
+    pub gap_size: f32,
+    /// How the opening moves from here on, if it does.
+    #[serde(skip)]
+    pub motion: Option<PairMotion>,
+}
+
+impl NextObstacle {
+    /// Where the opening is `ticks` from now.
+    pub fn gap_in(&self, ticks: u64) -> Gap {
+        match self.motion {
+            Some(motion) => motion.gap_in(ticks),
+            None => Gap {
+                y: self.gap_y,
+                size: self.gap_size,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Observation {
+    pub bird_y: f32,
+    /// Upwards is positive, in 'pixels/second'.
+    pub bird_velocity: f32,
+    /// The next two pairs of obstacles, nearest first.
+    pub obstacles: [Option<NextObstacle>; 2],
+}
+
+pub(crate) type PlayerQuery<'w, 's> =
+    Query<'w, 's, (&'static Transform, &'static Velocity), With<Player>>;
+pub(crate) type GapQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Transform,
+        &'static Gap,
+        Option<&'static PairMotion>,
+    ),
+>;
+
+impl Observation {
+    pub(crate) fn observe(
+        config: &GameConfig,
+        player_query: &PlayerQuery,
+        gap_query: &GapQuery,
+    ) -> Self {
+        // A pair is behind the bird once its right edge has gone past the
+        // bird's left edge
+        let reach = config.sprite_size / 2.0 + config.obstacle_width;
+
+        let (bird_x, bird_y, bird_velocity) = player_query
+            .iter()
+            .next()
+            .map_or((0.0, 0.0, 0.0), |(transform, velocity)| {
+                (
+                    transform.translation.x,
+                    transform.translation.y,
+                    velocity.linvel.y,
+                )
+            });
+
+        let mut ahead: Vec<NextObstacle> = gap_query
+            .iter()
+            .map(|(transform, gap, motion)| NextObstacle {
+                distance: transform.translation.x - bird_x,
+                gap_y: gap.y,
+                gap_size: gap.size,
+                motion: motion.copied(),
+            })
+            .filter(|next| next.distance > -reach)
+            .collect();
+        ahead.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+
+        Observation {
+            bird_y,
+            bird_velocity,
+            obstacles: [ahead.first().copied(), ahead.get(1).copied()],
+        }
+    }
+}
+
+/// One run of the game at a time, played by an agent instead of the keyboard.
+pub struct Env {
+    app: App,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Env::new()
+    }
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Env::with_config(GameConfig::default())
+    }
+
+    pub fn with_config(config: GameConfig) -> Self {
+        let mut app = headless_app();
+        app.insert_resource(config)
+            // Nothing presses it, but the game expects one to be picked
+            .insert_resource(ActionKey(ActionButton::Key(KeyCode::Space)));
+        app.update();
+
+        Env { app }
+    }
+
+    /// Starts a new run on the course for `seed`.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.app.insert_resource(GameSeed::fixed(seed));
+
+        let mut state = self.app.world.resource_mut::<State<AppState>>();
+        if *state.current() == AppState::Menu {
+            state.set(AppState::InGame).unwrap();
+        } else {
+            state.replace(AppState::Restarting).unwrap();
+        }
+        self.app.update();
+
+        self.observe()
+    }
+
+    /// Plays one tick. Once the run is over, it stays over until the next
+    /// [`Env::reset`].
+    pub fn step(&mut self, action: Action) -> (Observation, f32, bool) {
+        if self.done() {
+            return (self.observe(), 0.0, true);
+        }
+
+        let score = self.score();
+        self.app.world.resource_mut::<FlapRequest>().0 = action == Action::Flap;
+        self.app.update();
+
+        let done = self.done();
+        let reward = if done {
+            DEATH_REWARD
+        } else {
+            ALIVE_REWARD + SCORE_REWARD * (self.score() - score) as f32
+        };
+
+        (self.observe(), reward, done)
+    }
+
+    /// Obstacles passed in the current run.
+    pub fn score(&self) -> u128 {
+        self.app.world.resource::<Score>().0
+    }
+
+    fn done(&self) -> bool {
+        *self.app.world.resource::<State<AppState>>().current()
+            != AppState::InGame
+    }
+
+    fn observe(&mut self) -> Observation {
+        let mut state =
+            SystemState::<(Res<GameConfig>, PlayerQuery, GapQuery)>::new(
+                &mut self.app.world,
+            );
+        let (config, player_query, gap_query) = state.get(&self.app.world);
+        Observation::observe(&config, &player_query, &gap_query)
+    }
+}
@@ -0,0 +1,225 @@
+//! A see-through bird that flies a previous run of the same course, to race
+//! against.
+//!
+//! The ghost replays the recorded flaps with the same physics as the player,
+//! but has no collider, so it never touches an obstacle or ends the run.
+
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+use crate::replay::{RecordedFlaps, Replay, ReplayPlayback, RunTick};
+use crate::seed::GameSeed;
+use crate::storage::GameStorage;
+use crate::{player_mass, GameMode, Score};
+
+/// How many courses the best runs are remembered for.
+pub const GHOST_SLOTS: usize = 10;
+
+const STORAGE_KEY: &str = "ghosts";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BestRun {
+    pub score: u128,
+    pub replay: Replay,
+}
+
+/// The best run on each of the courses played most recently with a fixed
+/// seed, newest first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BestRuns {
+    runs: Vec<BestRun>,
+}
+
+impl BestRuns {
+    pub fn get(&self, seed: u64) -> Option<&BestRun> {
+        self.runs.iter().find(|run| run.replay.seed == seed)
+    }
+
+    /// Keeps `run` if it beats the best run on its course, and returns
+    /// whether it did.
+    pub fn record(&mut self, run: BestRun) -> bool {
+        let seed = run.replay.seed;
+        let previous =
+            self.runs.iter().position(|other| other.replay.seed == seed);
+        if let Some(index) = previous {
+            if self.runs[index].score >= run.score {
+                return false;
+            }
+            self.runs.remove(index);
+        }
+
+        self.runs.insert(0, run);
+        self.runs.truncate(GHOST_SLOTS);
+        true
+    }
+
+    fn load(storage: &GameStorage) -> Self {
+        storage
+            .load(STORAGE_KEY)
+            .and_then(|saved| match ron::from_str(&saved) {
+                Ok(best_runs) => Some(best_runs),
+                Err(err) => {
+                    warn!("Discarding unreadable ghosts: {}", err);
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    fn save(&self, storage: &GameStorage) {
+        let result = ron::to_string(self)
+            .map_err(|err| err.to_string())
+            .and_then(|saved| {
+                storage
+                    .save(STORAGE_KEY, &saved)
+                    .map_err(|err| err.to_string())
+            });
+
+        if let Err(err) = result {
+            warn!("Unable to save ghosts: {}", err);
+        }
+    }
+}
+
+/// Races `replay` instead of the personal best, on its course.
+pub fn race_replay(app: &mut App, replay: Replay) {
+    app.insert_resource(GameSeed::fixed(replay.seed))
+        .insert_resource(LoadedGhost(replay));
+}
+
+/// A replay picked to race against, used while its course is played.
+pub(crate) struct LoadedGhost(Replay);
+
+#[derive(Component)]
+pub(crate) struct Ghost(ReplayPlayback);
+
+pub(crate) fn load_best_runs(
+    mut best_runs: ResMut<BestRuns>,
+    storage: Res<GameStorage>,
+) {
+    *best_runs = BestRuns::load(&storage);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn spawn_ghost(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    rapier_context: Res<RapierContext>,
+    seed: Res<GameSeed>,
+    loaded_ghost: Option<Res<LoadedGhost>>,
+    best_runs: Res<BestRuns>,
+    mode: Res<GameMode>,
+) {
+    // There are birds enough to race already
+    if matches!(*mode, GameMode::Versus | GameMode::Online) {
+        return;
+    }
+    // Best runs are kept by seed, which doesn't say what a course looks like
+    if *mode == GameMode::Course {
+        return;
+    }
+
+    let replay = match loaded_ghost {
+        Some(loaded) if loaded.0.seed == seed.seed() => loaded.0.clone(),
+        _ => match best_runs.get(seed.seed()) {
+            Some(best) => best.replay.clone(),
+            None => return,
+        },
+    };
+
+    // Without a collider the body has no mass of its own, so give it the
+    // player's or the flaps would throw it around differently
+    let mass = player_mass(&config, &rapier_context);
+
+    commands
+        .spawn()
+        .insert_bundle(SpriteBundle {
+            texture: asset_server.load(Path::new("textures").join("bevy.png")),
+            sprite: Sprite {
+                color: Color::rgba(1.0, 1.0, 1.0, 0.4),
+                custom_size: Some(Vec2::new(
+                    config.sprite_size,
+                    config.sprite_size,
+                )),
+                ..default()
+            },
+            // In front of the background, which sits at the same depth as
+            // the player
+            transform: Transform::from_xyz(0.0, 0.0, 0.5),
+            ..default()
+        })
+        .insert(RigidBody::Dynamic)
+        .insert(ExternalImpulse::default())
+        .insert(AdditionalMassProperties::Mass(mass))
+        .insert(GravityScale(config.gravity_scale))
+        .insert(Ghost(ReplayPlayback::new(replay)));
+}
+
+pub(crate) fn move_ghost(
+    mut commands: Commands,
+    tick: Res<RunTick>,
+    config: Res<GameConfig>,
+    mut ghost_query: Query<(
+        Entity,
+        &mut Ghost,
+        &mut ExternalImpulse,
+        &Transform,
+    )>,
+) {
+    for (entity, mut ghost, mut impulse, transform) in &mut ghost_query {
+        // The recorded run is over once the ghost drops out of view
+        if transform.translation.y < -config.height {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        impulse.impulse = if ghost.0.flaps_at(tick.0) {
+            Vec2::new(0.0, config.impulse)
+        } else {
+            Vec2::ZERO
+        };
+    }
+}
+
+pub(crate) fn despawn_ghost(
+    mut commands: Commands,
+    ghost_query: Query<Entity, With<Ghost>>,
+) {
+    for entity in &ghost_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Keeps the run as the ghost of its course if it's the best one yet. Only
+/// fixed seeds come around again, so other runs are left out.
+pub(crate) fn record_best_run(
+    mode: Res<GameMode>,
+    score: Res<Score>,
+    seed: Res<GameSeed>,
+    config: Res<GameConfig>,
+    flaps: Res<RecordedFlaps>,
+    mut best_runs: ResMut<BestRuns>,
+    storage: Res<GameStorage>,
+) {
+    if matches!(
+        *mode,
+        GameMode::Replay | GameMode::Demo | GameMode::Versus | GameMode::Course
+    ) || !seed.is_fixed()
+    {
+        return;
+    }
+
+    let run = BestRun {
+        score: score.0,
+        replay: flaps.to_replay(&seed, &config),
+    };
+    if best_runs.record(run) {
+        info!("New best run on seed {}: {}", seed.seed(), score.0);
+        best_runs.save(&storage);
+    }
+}
@@ -0,0 +1,249 @@
+//! The table of best runs, kept in [`GameStorage`] between sessions.
+
+use std::path::Path;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::Stopwatch;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::daily::DailyScores;
+use crate::replay::TICK_SECS;
+use crate::storage::GameStorage;
+use crate::{ActionKey, GameMode, Score};
+
+/// How many runs the table remembers.
+pub const HIGH_SCORE_SLOTS: usize = 10;
+
+const STORAGE_KEY: &str = "high_scores";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighScoreEntry {
+    pub score: u128,
+    pub date: DateTime<Utc>,
+    pub duration_secs: f32,
+    pub action_key: String,
+}
+
+/// The best runs so far, highest score first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    entries: Vec<HighScoreEntry>,
+}
+
+impl HighScores {
+    pub fn entries(&self) -> &[HighScoreEntry] {
+        &self.entries
+    }
+
+    /// Adds a run to the table and returns its rank, or `None` if it didn't
+    /// make the cut. Ties go to the run that got there first.
+    pub fn record(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        let rank = self
+            .entries
+            .iter()
+            .position(|other| other.score < entry.score)
+            .unwrap_or(self.entries.len());
+
+        if rank >= HIGH_SCORE_SLOTS {
+            return None;
+        }
+
+        self.entries.insert(rank, entry);
+        self.entries.truncate(HIGH_SCORE_SLOTS);
+        Some(rank)
+    }
+
+    fn load(storage: &GameStorage) -> Self {
+        storage
+            .load(STORAGE_KEY)
+            .and_then(|saved| match ron::from_str(&saved) {
+                Ok(high_scores) => Some(high_scores),
+                Err(err) => {
+                    warn!("Discarding unreadable high scores: {}", err);
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    fn save(&self, storage: &GameStorage) {
+        let result = ron::to_string(self)
+            .map_err(|err| err.to_string())
+            .and_then(|saved| {
+                storage
+                    .save(STORAGE_KEY, &saved)
+                    .map_err(|err| err.to_string())
+            });
+
+        if let Err(err) = result {
+            warn!("Unable to save high scores: {}", err);
+        }
+    }
+}
+
+/// How long the current run has been going, not counting pauses.
+#[derive(Default)]
+pub(crate) struct RunClock(pub(crate) Stopwatch);
+
+#[derive(Component)]
+pub(crate) struct HighScoreText;
+
+pub(crate) fn load_high_scores(
+    mut high_scores: ResMut<HighScores>,
+    storage: Res<GameStorage>,
+) {
+    *high_scores = HighScores::load(&storage);
+}
+
+pub(crate) fn tick_run_clock(mut clock: ResMut<RunClock>) {
+    clock.0.tick(Duration::from_secs_f32(TICK_SECS));
+}
+
+/// Endless runs only, the daily challenge keeps its own scores.
+pub(crate) fn record_high_score(
+    mode: Res<GameMode>,
+    score: Res<Score>,
+    clock: Res<RunClock>,
+    action_key: Res<ActionKey>,
+    mut high_scores: ResMut<HighScores>,
+    storage: Res<GameStorage>,
+) {
+    if *mode != GameMode::Endless {
+        return;
+    }
+
+    let entry = HighScoreEntry {
+        score: score.0,
+        date: Utc::now(),
+        duration_secs: clock.0.elapsed_secs(),
+        action_key: action_key.0.to_string(),
+    };
+
+    if let Some(rank) = high_scores.record(entry) {
+        info!("New high score #{}: {}", rank + 1, score.0);
+        high_scores.save(&storage);
+    }
+}
+
+fn title(asset_server: &AssetServer, title: &str) -> TextSection {
+    TextSection::new(
+        format!("{}\n", title),
+        TextStyle {
+            font: asset_server
+                .load(Path::new("fonts").join("FiraSans-Bold.ttf")),
+            font_size: 36.0,
+            color: Color::GOLD,
+        },
+    )
+}
+
+fn row_style(asset_server: &AssetServer) -> TextStyle {
+    TextStyle {
+        font: asset_server.load(Path::new("fonts").join("FiraMono-Medium.ttf")),
+        font_size: 24.0,
+        color: Color::WHITE,
+    }
+}
+
+fn high_score_sections(
+    asset_server: &AssetServer,
+    high_scores: &HighScores,
+) -> Vec<TextSection> {
+    let style = row_style(asset_server);
+    let mut sections = vec![title(asset_server, "High Scores")];
+    sections.extend(high_scores.entries().iter().enumerate().map(
+        |(rank, entry)| {
+            let minutes = entry.duration_secs as u32 / 60;
+            let seconds = entry.duration_secs as u32 % 60;
+            TextSection::new(
+                format!(
+                    "{:>2}. {:>4}  {:>2}:{:02}  {:<6} {}\n",
+                    rank + 1,
+                    entry.score,
+                    minutes,
+                    seconds,
+                    entry.action_key,
+                    entry.date.format("%Y-%m-%d"),
+                ),
+                style.clone(),
+            )
+        },
+    ));
+    sections
+}
+
+fn daily_sections(
+    asset_server: &AssetServer,
+    daily_scores: &DailyScores,
+) -> Vec<TextSection> {
+    let style = row_style(asset_server);
+    let mut sections = vec![title(asset_server, "Daily Challenge")];
+    for (label, entry) in
+        [("Latest", daily_scores.latest), ("Best", daily_scores.best)]
+    {
+        if let Some(entry) = entry {
+            sections.push(TextSection::new(
+                format!("{:<6} {:>4}  {}\n", label, entry.score, entry.date),
+                style.clone(),
+            ));
+        }
+    }
+    sections
+}
+
+fn spawn_score_text(commands: &mut Commands, sections: Vec<TextSection>) {
+    commands
+        .spawn_bundle(TextBundle::from_sections(sections).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(90.0),
+                left: Val::Px(30.0),
+                ..default()
+            },
+            ..default()
+        }))
+        .insert(HighScoreText);
+}
+
+pub(crate) fn spawn_high_score_text(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    high_scores: Res<HighScores>,
+) {
+    spawn_score_text(
+        &mut commands,
+        high_score_sections(&asset_server, &high_scores),
+    );
+}
+
+/// Shows the table the run that just ended was counted in, if it was counted
+/// at all.
+pub(crate) fn spawn_run_scores(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mode: Res<GameMode>,
+    high_scores: Res<HighScores>,
+    daily_scores: Res<DailyScores>,
+) {
+    let sections = match *mode {
+        GameMode::Endless => high_score_sections(&asset_server, &high_scores),
+        GameMode::Daily => daily_sections(&asset_server, &daily_scores),
+        GameMode::Versus
+        | GameMode::Replay
+        | GameMode::Demo
+        | GameMode::Online
+        | GameMode::Course => return,
+    };
+    spawn_score_text(&mut commands, sections);
+}
+
+pub(crate) fn despawn_high_score_text(
+    mut commands: Commands,
+    text_query: Query<Entity, With<HighScoreText>>,
+) {
+    for entity in &text_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,269 @@
+//! Global scores, kept by the leaderboard server in `leaderboard/`.
+//!
+//! When an endless run ends, its score is sent along with the seed and the
+//! replay. The server plays the replay itself and only takes the score if the
+//! run really ends on it, then answers with where the run ranks. That is the
+//! whole of the anti-cheat, and why runs aren't signed: a key shipped with
+//! the game to sign scores could be pulled out of it, a replay that doesn't
+//! score can't be faked.
+//!
+//! Runs are sent one at a time from a thread of their own, posted as JSON to
+//! `/scores`:
+//!
+//! ```text
+//! {"score":3,"seed":1234,"replay":{"seed":1234,"config_hash":99,"flaps":[12,40]}}
+//! ```
+//!
+//! and the answer is a [`Ranking`], or an error status with the reason in the
+//! body.
+
+use std::io;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+use crate::replay::{RecordedFlaps, Replay};
+use crate::seed::GameSeed;
+use crate::{GameMode, Score};
+
+/// How many of the best scores come back with a ranking.
+pub const TOP_SCORES: usize = 5;
+
+/// How long the server has to take a run.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the server has to answer a run, which it plays back first.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A finished run, as sent to the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Submission {
+    pub score: u128,
+    pub seed: u64,
+    pub replay: Replay,
+}
+
+/// Where a submitted run places among every run the server took.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ranking {
+    /// Counting from 1, runs with the same score share a rank.
+    pub rank: u64,
+    pub total: u64,
+    /// The best scores, highest first.
+    pub top: Vec<u128>,
+}
+
+/// The server runs are sent to, one at a time by a thread of its own.
+pub(crate) struct Leaderboard {
+    /// Runs for the thread to send, numbered.
+    runs: Mutex<Sender<(u64, Submission)>>,
+    /// The server's answers, numbered like the runs they are for.
+    answers: Mutex<Receiver<(u64, io::Result<Ranking>)>>,
+    /// How many runs were sent so far.
+    sent: u64,
+    /// The run whose answer is still to come.
+    pending: Option<u64>,
+}
+
+/// The ranking of the run that just ended, once the server answered.
+pub struct GlobalRank(pub Ranking);
+
+#[derive(Component)]
+pub(crate) struct RankingText;
+
+/// Sends every endless run to the server at `url`, such as
+/// `http://127.0.0.1:8080`.
+pub fn use_server(app: &mut App, url: &str) -> io::Result<()> {
+    server_addr(url)?;
+    info!("Sending scores to {}", url);
+    let (runs, queued) = mpsc::channel();
+    let (answered, answers) = mpsc::channel();
+    let url = url.to_string();
+    thread::spawn(move || {
+        let agent = agent();
+        for (run, submission) in queued {
+            // Stops once the game is gone
+            if answered
+                .send((run, send(&agent, &url, &submission)))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    app.insert_resource(Leaderboard {
+        runs: Mutex::new(runs),
+        answers: Mutex::new(answers),
+        sent: 0,
+        pending: None,
+    });
+    Ok(())
+}
+
+/// Posts `submission` to the server at `url` and waits for its ranking.
+pub fn submit(url: &str, submission: &Submission) -> io::Result<Ranking> {
+    send(&agent(), url, submission)
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(ANSWER_TIMEOUT)
+        .timeout_write(CONNECT_TIMEOUT)
+        .build()
+}
+
+/// The `host:port` of an `http://` URL.
+fn server_addr(url: &str) -> io::Result<&str> {
+    url.strip_prefix("http://")
+        .map(|rest| rest.trim_end_matches('/'))
+        .filter(|addr| !addr.is_empty() && !addr.contains('/'))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("expected a URL such as http://host:port, got {}", url),
+            )
+        })
+}
+
+fn send(
+    agent: &ureq::Agent,
+    url: &str,
+    submission: &Submission,
+) -> io::Result<Ranking> {
+    let scores = format!("http://{}/scores", server_addr(url)?);
+    let response = agent
+        .post(&scores)
+        .set("Content-Type", "application/json")
+        .send_string(&serde_json::to_string(submission)?)
+        .map_err(|err| match err {
+            ureq::Error::Status(status, response) => io::Error::other(format!(
+                "the server answered {}: {}",
+                status,
+                response.into_string().unwrap_or_default().trim()
+            )),
+            ureq::Error::Transport(err) => io::Error::other(err),
+        })?;
+    Ok(serde_json::from_str(&response.into_string()?)?)
+}
+
+/// Sends the run that just ended, without holding up the game.
+pub(crate) fn submit_run(
+    mode: Res<GameMode>,
+    leaderboard: Option<ResMut<Leaderboard>>,
+    score: Res<Score>,
+    seed: Res<GameSeed>,
+    config: Res<GameConfig>,
+    flaps: Res<RecordedFlaps>,
+) {
+    let mut leaderboard = match leaderboard {
+        Some(leaderboard) if *mode == GameMode::Endless => leaderboard,
+        _ => return,
+    };
+
+    let submission = Submission {
+        score: score.0,
+        seed: seed.seed(),
+        replay: flaps.to_replay(&seed, &config),
+    };
+    leaderboard.sent += 1;
+    let run = leaderboard.sent;
+    if leaderboard
+        .runs
+        .lock()
+        .unwrap()
+        .send((run, submission))
+        .is_err()
+    {
+        warn!("The leaderboard is no longer sent runs");
+        return;
+    }
+    leaderboard.pending = Some(run);
+}
+
+/// Shows where the run ranks once the server has answered.
+pub(crate) fn show_ranking(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    leaderboard: Option<ResMut<Leaderboard>>,
+) {
+    let mut leaderboard = match leaderboard {
+        Some(leaderboard) => leaderboard,
+        None => return,
+    };
+    let run = match leaderboard.pending {
+        Some(run) => run,
+        None => return,
+    };
+    // Answers for runs that are gone are passed over
+    let answer = leaderboard
+        .answers
+        .lock()
+        .unwrap()
+        .try_iter()
+        .find(|&(answered, _)| answered == run);
+    let ranking = match answer {
+        None => return,
+        Some((_, Err(err))) => {
+            warn!("The leaderboard didn't take the score: {}", err);
+            None
+        }
+        Some((_, Ok(ranking))) => Some(ranking),
+    };
+    leaderboard.pending = None;
+    let ranking = match ranking {
+        Some(ranking) => ranking,
+        None => return,
+    };
+
+    let mut text =
+        format!("Global rank #{} of {}", ranking.rank, ranking.total);
+    for (place, score) in ranking.top.iter().enumerate() {
+        text.push_str(&format!("\n{}. {}", place + 1, score));
+    }
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                text,
+                TextStyle {
+                    font: asset_server
+                        .load(Path::new("fonts").join("FiraSans-Bold.ttf")),
+                    font_size: 30.0,
+                    color: Color::GOLD,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(10.0),
+                    right: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(RankingText);
+    commands.insert_resource(GlobalRank(ranking));
+}
+
+pub(crate) fn despawn_ranking(
+    mut commands: Commands,
+    leaderboard: Option<ResMut<Leaderboard>>,
+    text_query: Query<Entity, With<RankingText>>,
+) {
+    // An answer still on its way is for a run that's gone
+    if let Some(mut leaderboard) = leaderboard {
+        leaderboard.pending = None;
+    }
+    commands.remove_resource::<GlobalRank>();
+    for entity in &text_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,135 +1,696 @@
-use bevy::input::ButtonState;
-use bevy::sprite::Anchor;
-use bevy::{input::keyboard::KeyboardInput, prelude::*};
+use bevy::asset::{AssetPlugin, AssetServerSettings};
+use bevy::ecs::schedule::ShouldRun;
+use bevy::input::{InputPlugin, InputSystem};
+use bevy::prelude::*;
+use bevy::window::{WindowFocused, WindowPlugin};
 use bevy_rapier2d::prelude::*;
 use rand::Rng;
 use std::path::Path;
+use std::time::Duration;
+
+use config::{GameConfig, GameConfigLoader, GameConfigPath};
+use controls::{ActionButton, Buttons, GameAction, InputBindings};
+use course::CoursePlayback;
+use daily::{DailyScores, StashedSeed};
+use difficulty::Difficulty;
+use ghost::BestRuns;
+use highscore::{HighScores, RunClock};
+use obstacle::{spawn_obstacle_pair, Motion, ObstacleStyle};
+use online::Relay;
+use replay::{RecordedFlaps, ReplayPlayback, RunTick, TICK_SECS};
+use seed::GameSeed;
+use storage::GameStorage;
+use versus::{Crashed, SecondActionKey};
+
+pub mod autopilot;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bridge;
+pub mod config;
+pub mod controls;
+pub mod course;
+pub mod daily;
+pub mod difficulty;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod editor;
+pub mod env;
+pub mod ghost;
+mod highscore;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod leaderboard;
+pub mod obstacle;
+pub mod online;
+pub mod replay;
+pub mod seed;
+pub mod storage;
+pub mod versus;
 
-pub const HEIGHT: f32 = 1000.0;
-pub const WIDTH: f32 = 500.0;
 pub const LAUNCHER_TITLE: &str = "Bevy Bird";
-pub const OBSTACLE_WIDTH: f32 = 50.0;
-pub const SCROLL_SPEED: f32 = -100.0;
-pub const IMPULSE: f32 = 25000.0;
-pub const DENSITY: f32 = 50.0;
-pub const GRAVITY_SCALE: f32 = 10.0;
 
-const SPRITE_SIZE: f32 = 100.0;
+/// Where new obstacles come in from the right.
+pub(crate) const OBSTACLE_SPAWN_X: f32 = 400.0;
 
-// The float value is the player movement speed in 'pixels/second'.
+/// Which player flies the bird, counting from 0.
 #[derive(Component)]
-struct Player;
+pub struct Player(pub usize);
 
+/// Anything the player dies on touching.
 #[derive(Component)]
-struct Obstacle;
+pub struct Obstacle;
 
+/// An obstacle the player scores a point for getting past.
 #[derive(Component)]
-struct InPlay;
-struct GameOver(bool);
+pub struct InPlay;
+
+/// The opening of a pair of obstacles, kept on the top one.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Gap {
+    /// Height of the middle of the opening.
+    pub y: f32,
+    pub size: f32,
+}
 
+/// A flap asked for by something other than the keyboard, such as an agent
+/// driving the game. It is used up on the next tick.
+#[derive(Default)]
+pub struct FlapRequest(pub bool);
+
+/// The birds whose button was pressed since the last tick. A frame can go by
+/// without a tick, so presses wait here for the next one.
+#[derive(Default)]
+struct PressedFlaps(Vec<usize>);
+
+/// Obstacles passed in the current run. Each bird also carries its own, which
+/// stops counting once it crashes.
 #[derive(Component)]
-struct Score(u128);
+pub struct Score(pub u128);
+
+/// Which kind of run the menu starts.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum GameMode {
+    #[default]
+    Endless,
+    /// The same course for everyone on a given UTC day.
+    Daily,
+    /// Plays back a recorded run instead of reading the keyboard.
+    Replay,
+    /// The autopilot flies behind the menu until someone touches the game.
+    Demo,
+    /// Two birds on the same course, the last one flying wins.
+    Versus,
+    /// Racing another player's bird through the relay.
+    Online,
+    /// A hand-built course, then the random generator.
+    Course,
+}
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
-enum AppState {
+pub enum AppState {
     Menu,
     InGame,
+    /// Pushed on top of `InGame` when the player dies, so the run is frozen
+    /// in place until the player picks what to do next.
+    GameOver,
+    /// Transient state that bounces straight back into `InGame` after the
+    /// previous run has been torn down.
+    Restarting,
+    /// Pushed on top of `InGame` while the run is on hold.
+    Paused,
+    /// Pushed on top of the menu or the pause overlay to rebind buttons.
+    Controls,
+    /// Building a course by hand, with test runs going back to it.
+    Editor,
 }
 
+/// The whole game on a window, as the launchers run it.
 pub fn app() -> App {
     let mut app = App::new();
+    let config = GameConfig::default();
     app.insert_resource(WindowDescriptor {
         title: LAUNCHER_TITLE.to_string(),
-        width: WIDTH,
-        height: HEIGHT,
+        width: config.width,
+        height: config.height,
         ..Default::default()
     })
+    // Hot-reload assets, so the game config can be tuned while playing
+    .insert_resource(AssetServerSettings {
+        watch_for_changes: cfg!(not(target_arch = "wasm32")),
+        ..default()
+    })
     .add_plugins(DefaultPlugins)
-    .add_startup_system(setup_graphics)
-    .add_startup_system(setup)
-    .add_system(infinite_scroll)
-    .add_state(AppState::Menu)
-    .add_system_set(
-        SystemSet::on_update(AppState::Menu).with_system(start_menu),
-    )
-    .add_system_set(
-        SystemSet::on_enter(AppState::InGame).with_system(spawn_player),
-    )
-    .add_system_set(
-        SystemSet::on_update(AppState::InGame).with_system(player_movement),
-    )
-    .add_system_set(
-        SystemSet::on_enter(AppState::InGame)
-            .with_system(spawn_initial_ostacles),
-    )
-    .add_system_set(
-        SystemSet::on_update(AppState::InGame)
-            .with_system(spawn_timer_obstacles),
-    )
-    .add_system_set(
-        SystemSet::on_update(AppState::InGame)
-            .with_system(destroy_obstacles),
-    )
-    .add_system_set(
-        SystemSet::on_update(AppState::InGame)
-            .with_system(detect_collision),
-    )
-    .add_system_set(
-        SystemSet::on_update(AppState::InGame)
-            .with_system(detect_game_over),
+    .add_plugin(BevyBirdPlugin { windowed: true });
+
+    app
+}
+
+/// The game without a window or renderer, for tests and simulations. Drive it
+/// with `App::update`, one tick per call, unless [`TickClock::real_time`] is
+/// inserted to keep pace with the clock.
+///
+/// The config file isn't loaded, so the game plays with whatever
+/// [`GameConfig`] is in the app, the defaults unless one is inserted.
+pub fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(WindowPlugin)
+        .add_plugin(AssetPlugin)
+        .add_plugin(BevyBirdPlugin { windowed: false });
+
+    app
+}
+
+/// The stages run on every tick, nested in [`TickStage::Tick`] ahead of
+/// [`CoreStage::Update`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+pub enum TickStage {
+    /// Runs the others, as many times over as the frame needs.
+    Tick,
+    /// Moving and spawning things, ahead of the physics.
+    Gameplay,
+    /// Once the physics has moved everything.
+    AfterPhysics,
+}
+
+/// How ticks are paced against the clock.
+pub struct TickClock {
+    /// Whether ticks keep pace with the clock, rather than going one per
+    /// update.
+    real_time: bool,
+    /// How far the game has fallen behind the clock.
+    behind: Duration,
+    /// Whether the current frame has run a tick yet.
+    ticking: bool,
+}
+
+impl TickClock {
+    /// A tick for every [`TICK_SECS`] gone by, whatever the refresh rate.
+    pub fn real_time() -> Self {
+        TickClock {
+            real_time: true,
+            behind: Duration::ZERO,
+            ticking: false,
+        }
+    }
+
+    /// A tick on every update, for driving the game by hand.
+    pub fn every_update() -> Self {
+        TickClock {
+            real_time: false,
+            ..TickClock::real_time()
+        }
+    }
+}
+
+/// The most ticks a frame catches up on, so a long stall, such as the window
+/// being dragged, doesn't fast-forward the run.
+const MAX_TICKS_BEHIND: u32 = 8;
+
+/// Runs a tick for every [`TICK_SECS`] gone by, so the game goes at the same
+/// speed whatever the refresh rate.
+fn next_tick(
+    time: Res<Time>,
+    state: Res<State<AppState>>,
+    mut clock: ResMut<TickClock>,
+) -> ShouldRun {
+    if !clock.real_time {
+        return ShouldRun::Yes;
+    }
+
+    let tick = Duration::from_secs_f32(TICK_SECS);
+    if !clock.ticking {
+        clock.behind =
+            (clock.behind + time.delta()).min(tick * MAX_TICKS_BEHIND);
+    } else if state.is_changed() {
+        // The tick ended or paused the run, which the frame sees to first
+        clock.ticking = false;
+        return ShouldRun::No;
+    }
+
+    if clock.behind < tick {
+        clock.ticking = false;
+        return ShouldRun::No;
+    }
+    clock.behind -= tick;
+    clock.ticking = true;
+    ShouldRun::YesAndCheckAgain
+}
+
+/// The game's states, systems and resources.
+pub struct BevyBirdPlugin {
+    /// Whether someone is playing in a window: the config file is loaded,
+    /// colliders can be drawn and the menu plays a demo. Sprites and text are
+    /// spawned either way, so headless tests can check what would be drawn.
+    pub windowed: bool,
+}
+
+impl Plugin for BevyBirdPlugin {
+    fn build(&self, app: &mut App) {
+        // Step the physics by the same amount every tick, so runs replay
+        // exactly
+        app.insert_resource(RapierConfiguration {
+            timestep_mode: TimestepMode::Fixed {
+                dt: TICK_SECS,
+                substeps: 1,
+            },
+            ..default()
+        })
+        .insert_resource(if self.windowed {
+            TickClock::real_time()
+        } else {
+            TickClock::every_update()
+        })
+        .add_plugin(
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0)
+                .with_default_system_setup(false),
+        )
+        .add_stage_before(CoreStage::Update, TickStage::Tick, tick_schedule())
+        .add_stage_before(
+            CoreStage::Last,
+            PhysicsStages::DetectDespawn,
+            physics_stage(PhysicsStages::DetectDespawn),
+        )
+        .add_asset::<GameConfig>()
+        .init_asset_loader::<GameConfigLoader>()
+        .add_system(config::apply_game_config)
+        .add_system(config::retune_entities.after(config::apply_game_config))
+        .add_startup_system(setup_graphics)
+        .add_startup_system(setup)
+        .add_startup_system(highscore::load_high_scores)
+        .add_startup_system(daily::load_daily_scores)
+        .add_startup_system(ghost::load_best_runs)
+        .add_startup_system(controls::load_bindings)
+        .add_system(controls::toggle_debug)
+        // Ahead of the ticks that move the opponent
+        .add_system_to_stage(CoreStage::PreUpdate, online::receive_messages)
+        .add_system(infinite_scroll)
+        .add_system(obstacle::fit_pipe_sprites)
+        .add_state(AppState::Menu)
+        .add_system_set(
+            SystemSet::on_enter(AppState::Menu).with_system(spawn_welcome_text),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Menu)
+                .with_system(highscore::spawn_high_score_text),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Menu)
+                .with_system(daily::spawn_mode_button)
+                .with_system(controls::spawn_controls_button),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Menu)
+                .with_system(start_menu)
+                .with_system(daily::select_mode)
+                .with_system(controls::open_controls),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Menu)
+                .with_system(highscore::despawn_high_score_text)
+                .with_system(daily::despawn_mode_button)
+                .with_system(controls::despawn_controls_button),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::InGame)
+                .with_system(spawn_player)
+                .with_system(daily::start_daily_run)
+                .with_system(course::start_course)
+                .with_system(replay::start_recording)
+                .with_system(ghost::spawn_ghost)
+                .with_system(online::spawn_opponent),
+        )
+        .add_system_set_to_stage(
+            CoreStage::PreUpdate,
+            SystemSet::new()
+                .with_run_criteria(outside_update(AppState::InGame))
+                .with_system(latch_flaps.after(InputSystem)),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::InGame)
+                .with_system(difficulty::update_difficulty)
+                .with_system(
+                    spawn_initial_ostacles.after(difficulty::update_difficulty),
+                ),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::InGame)
+                .with_system(teardown_run)
+                .with_system(daily::end_daily_run)
+                .with_system(ghost::despawn_ghost)
+                .with_system(online::despawn_opponent),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::GameOver)
+                .with_system(spawn_game_over_text)
+                .with_system(highscore::record_high_score)
+                .with_system(daily::record_daily_score)
+                .with_system(replay::save_replay)
+                .with_system(ghost::record_best_run)
+                .with_system(
+                    highscore::spawn_run_scores
+                        .after(highscore::record_high_score)
+                        .after(daily::record_daily_score),
+                ),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::GameOver)
+                .with_system(game_over_input),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::GameOver)
+                .with_system(despawn_game_over_text)
+                .with_system(highscore::despawn_high_score_text),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Restarting).with_system(restart_run),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame).with_system(pause_game),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(autopilot::end_demo.after(pause_game)),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::GameOver)
+                .with_system(autopilot::end_demo.after(game_over_input)),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Paused)
+                .with_system(freeze_physics)
+                .with_system(spawn_pause_overlay),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Paused).with_system(pause_menu),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Paused)
+                .with_system(unfreeze_physics)
+                .with_system(despawn_pause_overlay),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Controls)
+                .with_system(controls::spawn_controls_screen),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Controls)
+                .with_system(controls::rebind_controls),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Controls)
+                .with_system(controls::despawn_controls_screen),
+        )
+        // Resources
+        .init_resource::<GameConfig>()
+        .init_resource::<GameConfigPath>()
+        .insert_resource(Score(0))
+        .init_resource::<SpawnNextObstacle>()
+        .init_resource::<Difficulty>()
+        .init_resource::<GameSeed>()
+        .init_resource::<GameMode>()
+        .init_resource::<DailyScores>()
+        .init_resource::<StashedSeed>()
+        .init_resource::<RunClock>()
+        .init_resource::<RunTick>()
+        .init_resource::<RecordedFlaps>()
+        .init_resource::<FlapRequest>()
+        .init_resource::<PressedFlaps>()
+        .init_resource::<HighScores>()
+        .init_resource::<BestRuns>()
+        .init_resource::<InputBindings>()
+        .init_resource::<controls::Rebinding>()
+        .init_resource::<online::OpponentRun>()
+        .init_resource::<GameStorage>();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_system(bridge::apply_commands);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_system_set(
+            SystemSet::on_enter(AppState::GameOver)
+                .with_system(leaderboard::submit_run),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::GameOver)
+                .with_system(leaderboard::show_ranking),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::GameOver)
+                .with_system(leaderboard::despawn_ranking),
+        );
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.init_resource::<editor::CourseFile>()
+            .init_resource::<editor::EditedCourse>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Menu)
+                    .with_system(editor::spawn_editor_button),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu)
+                    .with_system(editor::open_editor),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Menu)
+                    .with_system(editor::despawn_editor_button),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::Editor)
+                    .with_system(editor::spawn_editor),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Editor)
+                    .with_system(editor::pan_camera)
+                    .with_system(editor::edit_pairs)
+                    .with_system(editor::save_course)
+                    .with_system(
+                        editor::draw_course
+                            .after(editor::edit_pairs)
+                            .after(editor::save_course),
+                    )
+                    .with_system(editor::test_from_here)
+                    .with_system(editor::leave_editor),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Editor)
+                    .with_system(editor::despawn_editor),
+            );
+
+        if self.windowed {
+            app.add_plugin(RapierDebugRenderPlugin::default().disabled())
+                .add_startup_system(config::load_game_config)
+                // Nobody watches a headless game
+                .add_system_set(
+                    SystemSet::on_update(AppState::Menu)
+                        .with_system(autopilot::start_demo),
+                );
+        }
+    }
+}
+
+/// Runs a system set while `state` is the current one, in stages other than
+/// [`CoreStage::Update`]. [`SystemSet::on_update`] keeps checking again until
+/// the state driver, which only runs in that stage, tells it to stop.
+fn outside_update(
+    state: AppState,
+) -> impl FnMut(Res<State<AppState>>) -> ShouldRun {
+    move |current: Res<State<AppState>>| {
+        if *current.current() == state {
+            ShouldRun::Yes
+        } else {
+            ShouldRun::No
+        }
+    }
+}
+
+/// One of the physics stages, which run where the game puts them rather than
+/// where the physics plugin would.
+fn physics_stage(stage: PhysicsStages) -> SystemStage {
+    SystemStage::parallel()
+        .with_system_set(RapierPhysicsPlugin::<NoUserData>::get_systems(stage))
+}
+
+/// Everything that moves the game on by a tick, the physics included.
+fn tick_schedule() -> Schedule {
+    let mut tick = Schedule::default()
+        .with_run_criteria(next_tick)
+        .with_stage(TickStage::Gameplay, SystemStage::parallel())
+        .with_stage(
+            PhysicsStages::SyncBackend,
+            physics_stage(PhysicsStages::SyncBackend),
+        )
+        .with_stage(
+            PhysicsStages::StepSimulation,
+            physics_stage(PhysicsStages::StepSimulation),
+        )
+        .with_stage(
+            PhysicsStages::Writeback,
+            physics_stage(PhysicsStages::Writeback),
+        )
+        .with_stage(TickStage::AfterPhysics, SystemStage::parallel());
+
+    tick.add_system_set_to_stage(
+        TickStage::Gameplay,
+        SystemSet::new()
+            .with_run_criteria(outside_update(AppState::InGame))
+            .with_system(autopilot::fly.before(player_movement))
+            .with_system(player_movement)
+            .with_system(ghost::move_ghost)
+            .with_system(online::move_opponent.after(replay::advance_tick))
+            .with_system(obstacle::move_obstacles.after(player_movement))
+            .with_system(
+                replay::advance_tick
+                    .after(player_movement)
+                    .after(ghost::move_ghost),
+            )
+            .with_system(difficulty::update_difficulty)
+            .with_system(
+                difficulty::apply_difficulty
+                    .after(difficulty::update_difficulty),
+            )
+            .with_system(spawn_timer_obstacles)
+            // At the speed the pipes move on this tick
+            .with_system(
+                course::spawn_course_obstacles
+                    .after(difficulty::apply_difficulty),
+            )
+            .with_system(destroy_obstacles)
+            .with_system(detect_collision)
+            .with_system(display_intersection_info)
+            .with_system(highscore::tick_run_clock),
     )
-    .add_system_set(
-        SystemSet::on_update(AppState::InGame)
-            .with_system(display_intersection_info),
+    .add_system_set_to_stage(
+        TickStage::Gameplay,
+        // Obstacles keep going behind the game over screen
+        SystemSet::new()
+            .with_run_criteria(outside_update(AppState::GameOver))
+            .with_system(obstacle::move_obstacles),
     )
-    // Debug
-    // .add_plugin(RapierDebugRenderPlugin::default())
-    .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
-    // Resources
-    .insert_resource(GameOver(false))
-    .insert_resource(Score(0))
-    .init_resource::<SpawnNextObstacle>();
+    // So the opponent sees where the tick left the bird
+    .add_system_to_stage(TickStage::AfterPhysics, online::send_bird);
 
-    app
+    // Likewise the agents
+    #[cfg(not(target_arch = "wasm32"))]
+    tick.add_system_to_stage(TickStage::AfterPhysics, bridge::send_observation);
+
+    tick
 }
 
 struct SpawnNextObstacle {
     event_timer: Timer,
 }
 
-impl Default for SpawnNextObstacle {
-    fn default() -> Self {
+impl SpawnNextObstacle {
+    fn new(interval_secs: f32) -> Self {
         SpawnNextObstacle {
-            event_timer: Timer::from_seconds(3.0, true),
+            event_timer: Timer::from_seconds(interval_secs, true),
         }
     }
 }
 
+impl FromWorld for SpawnNextObstacle {
+    fn from_world(world: &mut World) -> Self {
+        let config = world.get_resource_or_insert_with(GameConfig::default);
+        SpawnNextObstacle::new(config.spawn_interval_secs)
+    }
+}
+
+/// The button picked on the menu to flap with, on whichever device it was
+/// pressed. Each bird carries its player's as a component too.
 #[derive(Component)]
-struct ActionKey(KeyCode);
+pub struct ActionKey(pub ActionButton);
+
+const WELCOME_PROMPT: &str = "Choose your \naction button!";
+
+#[allow(clippy::too_many_arguments)]
 fn start_menu(
     mut cmds: Commands,
     mut state: ResMut<State<AppState>>,
-    mut key_evr: EventReader<KeyboardInput>,
+    mode: Res<GameMode>,
+    buttons: Buttons,
+    interaction_query: Query<&Interaction>,
+    mut text_query: Query<&mut Text, With<WelcomeText>>,
+    relay: Option<Res<Relay>>,
+    mut pressed: Local<Vec<ActionButton>>,
+    mut first_pick: Local<Option<ActionButton>>,
 ) {
-    for ev in &mut key_evr.iter() {
-        match ev.state {
-            ButtonState::Pressed => {}
-            ButtonState::Released => {
-                info!("Action key is: : {:?} ({})", ev.key_code, ev.scan_code);
-                if let Some(key_code) = ev.key_code {
-                    state.set(AppState::InGame).unwrap();
-                    cmds.insert_resource(ActionKey(key_code));
-                }
-            }
+    // Switching away from versus drops player one's pick
+    if *mode != GameMode::Versus {
+        *first_pick = None;
+    }
+    // Online runs wait for the relay to pick the course
+    let waiting = relay.is_some_and(|relay| !relay.matched());
+    let prompt = match (*mode, *first_pick) {
+        (GameMode::Versus, None) => "Player 1, choose\nyour action button!",
+        (GameMode::Versus, Some(_)) => "Player 2, choose\nyour action button!",
+        _ if waiting => "Waiting for\nan opponent...",
+        _ => WELCOME_PROMPT,
+    };
+    for mut text in &mut text_query {
+        if text.sections[0].value != prompt {
+            text.sections[0].value = prompt.to_string();
+        }
+    }
+
+    // Clicking or tapping the menu's own buttons doesn't pick one
+    let on_ui = interaction_query
+        .iter()
+        .any(|interaction| *interaction != Interaction::None);
+    for button in buttons.all_just_pressed() {
+        if !(button.is_pointer() && on_ui) && !pressed.contains(&button) {
+            pressed.push(button);
         }
     }
+
+    // Only a button pressed on the menu counts, not one held from before it.
+    // Escape is reserved for backing out to the menu, Tab for picking the
+    // game mode, F1 for the controls and F2 for the editor. Player two needs
+    // a button of their own
+    let released: Vec<ActionButton> = buttons.all_just_released().collect();
+    let picked = released.iter().copied().find(|button| {
+        pressed.contains(button)
+            && Some(*button) != *first_pick
+            && !matches!(
+                button,
+                ActionButton::Key(
+                    KeyCode::Escape | KeyCode::Tab | KeyCode::F1 | KeyCode::F2
+                )
+            )
+    });
+    pressed.retain(|button| !released.contains(button));
+
+    let button = match picked {
+        Some(button) if !waiting => button,
+        _ => return,
+    };
+    match first_pick.take() {
+        None if *mode == GameMode::Versus => {
+            info!("Player 1's action button is: {}", button);
+            *first_pick = Some(button);
+            return;
+        }
+        None => {
+            info!("Action button is: {}", button);
+            cmds.insert_resource(ActionKey(button));
+        }
+        Some(first) => {
+            info!("Player 2's action button is: {}", button);
+            cmds.insert_resource(ActionKey(first));
+            cmds.insert_resource(SecondActionKey(button));
+        }
+    }
+    state.set(AppState::InGame).unwrap();
 }
 
 #[derive(Component)]
-struct Background;
+pub struct Background;
 
-fn setup_graphics(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup_graphics(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+) {
     let texture_path = Path::new("textures");
     commands.spawn_bundle(Camera2dBundle {
         transform: Transform::from_xyz(0.0, 20.0, 50.0),
@@ -141,7 +702,10 @@ fn setup_graphics(mut commands: Commands, asset_server: Res<AssetServer>) {
         .spawn_bundle(SpriteBundle {
             // transform: Transform::from_xyz(0.0, 0.0, 0.0),
             sprite: Sprite {
-                custom_size: Some(Vec2::new(6.8 * WIDTH, 1.2 * HEIGHT)),
+                custom_size: Some(Vec2::new(
+                    6.8 * config.width,
+                    1.2 * config.height,
+                )),
                 ..default()
             },
             texture: asset_server.load(texture_path.join("background.png")),
@@ -149,7 +713,7 @@ fn setup_graphics(mut commands: Commands, asset_server: Res<AssetServer>) {
         })
         .insert(RigidBody::KinematicVelocityBased)
         .insert(Velocity {
-            linvel: Vec2::new(0.1 * SCROLL_SPEED, 0.0),
+            linvel: Vec2::new(0.1 * config.scroll_speed, 0.0),
             ..default()
         })
         .insert(Background);
@@ -157,9 +721,12 @@ fn setup_graphics(mut commands: Commands, asset_server: Res<AssetServer>) {
     // Spawn the next background plane
     commands
         .spawn_bundle(SpriteBundle {
-            transform: Transform::from_xyz(6.8 * WIDTH, 0.0, 0.0),
+            transform: Transform::from_xyz(6.8 * config.width, 0.0, 0.0),
             sprite: Sprite {
-                custom_size: Some(Vec2::new(6.8 * WIDTH, 1.2 * HEIGHT)),
+                custom_size: Some(Vec2::new(
+                    6.8 * config.width,
+                    1.2 * config.height,
+                )),
                 ..default()
             },
             texture: asset_server.load(texture_path.join("background.png")),
@@ -167,7 +734,7 @@ fn setup_graphics(mut commands: Commands, asset_server: Res<AssetServer>) {
         })
         .insert(RigidBody::KinematicVelocityBased)
         .insert(Velocity {
-            linvel: Vec2::new(0.1 * SCROLL_SPEED, 0.0),
+            linvel: Vec2::new(0.1 * config.scroll_speed, 0.0),
             ..default()
         })
         .insert(Background);
@@ -179,18 +746,26 @@ fn infinite_scroll(
     mut cmds: Commands,
     background_query: Query<(Entity, &Transform), With<Background>>,
     asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
 ) {
     for (entity, transform) in &background_query {
-        if transform.translation.x < -6.8 * WIDTH {
+        if transform.translation.x < -6.8 * config.width {
             let texture_path = Path::new("textures");
             info!("Despawning background text");
             cmds.entity(entity).despawn();
             info!("Spawning Next background");
             // Spawn Background
             cmds.spawn_bundle(SpriteBundle {
-                transform: Transform::from_xyz(6.8 * WIDTH - 2.0, 0.0, 0.0),
+                transform: Transform::from_xyz(
+                    6.8 * config.width - 2.0,
+                    0.0,
+                    0.0,
+                ),
                 sprite: Sprite {
-                    custom_size: Some(Vec2::new(6.8 * WIDTH, 1.2 * HEIGHT)),
+                    custom_size: Some(Vec2::new(
+                        6.8 * config.width,
+                        1.2 * config.height,
+                    )),
                     ..default()
                 },
                 texture: asset_server.load(texture_path.join("background.png")),
@@ -198,7 +773,7 @@ fn infinite_scroll(
             })
             .insert(RigidBody::KinematicVelocityBased)
             .insert(Velocity {
-                linvel: Vec2::new(0.1 * SCROLL_SPEED, 0.0),
+                linvel: Vec2::new(0.1 * config.scroll_speed, 0.0),
                 ..default()
             })
             .insert(Background);
@@ -209,119 +784,21 @@ fn infinite_scroll(
 fn spawn_initial_ostacles(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    difficulty: Res<Difficulty>,
+    course: Option<Res<CoursePlayback>>,
 ) {
-    let texture_path = Path::new("textures");
-
-    // Top Obstacle
-    commands
-        .spawn()
-        .insert_bundle(SpriteBundle {
-            sprite: Sprite {
-                flip_y: true,
-                custom_size: Some(Vec2::new(OBSTACLE_WIDTH * 2.0, 300.0)),
-                anchor: Anchor::TopCenter,
-                ..default()
-            },
-            // Top Collider Transform
-            transform: Transform::from_xyz(400.0, 500.0, 0.0),
-            texture: asset_server.load(texture_path.join("obstacle3.png")),
-            ..default()
-        })
-        // Top Obstacle Textures
-        .with_children(|children| {
-            // Middle Texture
-            children.spawn_bundle(SpriteBundle {
-                sprite: Sprite {
-                    flip_y: true,
-                    custom_size: Some(Vec2::new(
-                        OBSTACLE_WIDTH * 1.6,
-                        OBSTACLE_WIDTH * 3.2,
-                    )),
-                    ..default()
-                },
-                texture: asset_server.load(texture_path.join("obstacle2.png")),
-                transform: Transform::from_xyz(0.0, 70.0, 0.0),
-                ..default()
-            });
-            // Bottom Texture
-            children.spawn_bundle(SpriteBundle {
-                sprite: Sprite {
-                    flip_y: true,
-                    custom_size: Some(Vec2::new(
-                        OBSTACLE_WIDTH * 1.6,
-                        OBSTACLE_WIDTH * 3.2,
-                    )),
-                    ..default()
-                },
-                texture: asset_server.load(texture_path.join("obstacle2.png")),
-                transform: Transform::from_xyz(0.0, 70.0 * 2.0, 0.0),
-                ..default()
-            });
-        })
-        .insert(RigidBody::KinematicVelocityBased)
-        .insert(Collider::cuboid(OBSTACLE_WIDTH, 300.0))
-        .insert(Velocity {
-            linvel: Vec2::new(SCROLL_SPEED, 0.0),
-            angvel: 0.0,
-        })
-        .insert(ActiveCollisionTypes::all())
-        .insert(Obstacle)
-        .insert(InPlay);
-
-    // Bottom Obstacle
-    commands
-        .spawn()
-        .insert_bundle(SpriteBundle {
-            sprite: Sprite {
-                //  flip_y: true,
-                custom_size: Some(Vec2::new(OBSTACLE_WIDTH * 2.0, 300.0)),
-                anchor: Anchor::BottomCenter,
-                ..default()
-            },
-            // Bottom Collider Transform
-            transform: Transform::from_xyz(400.0, -500.0, 0.0),
-            texture: asset_server.load(texture_path.join("obstacle3.png")),
-            ..default()
-        })
-        // Bottom Obstacle Textures
-        .with_children(|children| {
-            children.spawn_bundle(SpriteBundle {
-                sprite: Sprite {
-                    flip_y: true,
-                    custom_size: Some(Vec2::new(
-                        OBSTACLE_WIDTH * 1.6,
-                        OBSTACLE_WIDTH * 3.2,
-                    )),
-                    ..default()
-                },
-                texture: asset_server.load(texture_path.join("obstacle2.png")),
-                transform: Transform::from_xyz(0.0, -70.0, 0.0),
-                ..default()
-            });
-
-            // Bottom Texture
-            children.spawn_bundle(SpriteBundle {
-                sprite: Sprite {
-                    flip_y: true,
-                    custom_size: Some(Vec2::new(
-                        OBSTACLE_WIDTH * 1.6,
-                        OBSTACLE_WIDTH * 3.2,
-                    )),
-                    ..default()
-                },
-                texture: asset_server.load(texture_path.join("obstacle2.png")),
-                transform: Transform::from_xyz(0.0, -70.0 * 2.0, 0.0),
-                ..default()
-            });
-        })
-        .insert(RigidBody::KinematicVelocityBased)
-        .insert(Collider::cuboid(OBSTACLE_WIDTH, 300.0))
-        .insert(Velocity {
-            linvel: Vec2::new(SCROLL_SPEED, 0.0),
-            angvel: 0.0,
-        })
-        .insert(ActiveCollisionTypes::all())
-        .insert(Obstacle);
+    // A course lays out its own pairs from the start
+    if course.is_none() {
+        let style = ObstacleStyle::new(&asset_server, &config, &difficulty);
+        spawn_obstacle_pair(
+            &mut commands,
+            OBSTACLE_SPAWN_X,
+            0.0,
+            difficulty.gap_size,
+            &style,
+        );
+    }
 
     // Floor Collider
     commands
@@ -349,205 +826,184 @@ fn spawn_initial_ostacles(
 fn destroy_obstacles(
     mut commands: Commands,
     q: Query<(Entity, &Obstacle, &Transform)>,
+    config: Res<GameConfig>,
 ) {
     for (e, _o, t) in q.iter() {
-        if t.translation.x < -(WIDTH - 100.0) {
+        if t.translation.x < -(config.width - 100.0) {
             commands.entity(e).despawn_recursive();
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_timer_obstacles(
     mut commands: Commands,
     mut timer: ResMut<SpawnNextObstacle>,
-    time: Res<Time>,
     asset_server: Res<AssetServer>,
     score: Res<Score>,
+    config: Res<GameConfig>,
+    difficulty: Res<Difficulty>,
+    course: Option<Res<CoursePlayback>>,
+    mut seed: ResMut<GameSeed>,
 ) {
-    let texture_path = Path::new("textures");
+    // Held back until a course is over, then a full interval after its last
+    // pair
+    if course.is_some_and(|course| !course.finished()) {
+        timer.event_timer.reset();
+        return;
+    }
+
     // Tick timer
-    timer.event_timer.tick(time.delta());
+    timer.event_timer.tick(Duration::from_secs_f32(TICK_SECS));
 
     if timer.event_timer.just_finished() && score.0 != 0 {
-        let rng_val = rand::thread_rng().gen_range(-1.0_f32..1.0_f32);
+        let rng_val = seed.rng().gen_range(-1.0_f32..1.0_f32);
 
-        let offset = 150.0 * rng_val; // Randomly shift obstacles to add variety.
+        let offset = config.random_offset * rng_val; // Randomly shift obstacles to add variety.
 
-        let squeeze_offset = 110.0 * (score.0 as f32 / 10.0); // Decrease the gap as the score increases
-        dbg!(squeeze_offset);
+        // The difficulty curve decides how tight the gap gets, and how likely
+        // the pair is to move
+        let mut style = ObstacleStyle::new(&asset_server, &config, &difficulty);
+        // The bird flies at x = 0
+        let arrival = OBSTACLE_SPAWN_X / -difficulty.scroll_speed / TICK_SECS;
+        style.motion = Motion::pick(
+            &difficulty.obstacle_weights,
+            seed.rng(),
+            arrival as u64,
+        );
+        spawn_obstacle_pair(
+            &mut commands,
+            OBSTACLE_SPAWN_X,
+            offset,
+            difficulty.gap_size,
+            &style,
+        );
+    }
+}
 
-        let top_obstacle_y = 500.0 + offset - squeeze_offset;
-        let bottom_obstacle_y = -500.0 + offset + squeeze_offset;
+fn spawn_player(
+    mut commands: Commands,
+    text_query: Query<Entity, With<WelcomeText>>,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    mode: Res<GameMode>,
+    action_key: Res<ActionKey>,
+    second_action_key: Option<Res<SecondActionKey>>,
+) {
+    let texture_path = Path::new("textures");
+    let versus = *mode == GameMode::Versus;
+    let mut action_keys = vec![action_key.0];
+    if versus {
+        action_keys.extend(second_action_key.map(|key| key.0));
+    }
 
-        // Top Obstacle
-        commands
-            .spawn()
-            .insert_bundle(SpriteBundle {
-                sprite: Sprite {
-                    flip_y: true,
-                    custom_size: Some(Vec2::new(OBSTACLE_WIDTH * 2.0, 300.0)),
-                    anchor: Anchor::TopCenter,
-                    ..default()
-                },
-                // Top Collider Transform
-                transform: Transform::from_xyz(400.0, top_obstacle_y, 0.0),
-                texture: asset_server.load(texture_path.join("obstacle3.png")),
-                ..default()
-            })
-            // Top Obstacle Extension
-            .with_children(|children| {
-                // Middle Texture
-                children.spawn_bundle(SpriteBundle {
-                    sprite: Sprite {
-                        flip_y: true,
-                        custom_size: Some(Vec2::new(
-                            OBSTACLE_WIDTH * 1.6,
-                            OBSTACLE_WIDTH * 3.2,
-                        )),
-                        ..default()
-                    },
-                    texture: asset_server
-                        .load(texture_path.join("obstacle2.png")),
-                    transform: Transform::from_xyz(0.0, 70.0, 0.0),
-                    ..default()
-                });
-
-                // Bottom Texture
-                children.spawn_bundle(SpriteBundle {
-                    sprite: Sprite {
-                        flip_y: true,
-                        custom_size: Some(Vec2::new(
-                            OBSTACLE_WIDTH * 1.6,
-                            OBSTACLE_WIDTH * 3.2,
-                        )),
-                        ..default()
-                    },
-                    texture: asset_server
-                        .load(texture_path.join("obstacle2.png")),
-                    transform: Transform::from_xyz(0.0, 70.0 * 2.0, 0.0),
-                    ..default()
-                });
-            })
-            .insert(RigidBody::KinematicVelocityBased)
-            .insert(Collider::cuboid(OBSTACLE_WIDTH, 300.0))
-            .insert(Velocity {
-                linvel: Vec2::new(SCROLL_SPEED, 0.0),
-                angvel: 0.0,
-            })
-            .insert(Obstacle)
-            .insert(ActiveCollisionTypes::all())
-            .insert(InPlay);
+    for (index, button) in action_keys.into_iter().enumerate() {
+        let (color, y) = if versus {
+            (versus::TINTS[index], versus::lane_y(index, &config))
+        } else {
+            (Color::WHITE, 0.0)
+        };
 
-        // Bottom Obstacle
+        // Spawn entity with `Player` struct as a component for access in movement query.
         commands
             .spawn()
             .insert_bundle(SpriteBundle {
+                texture: asset_server.load(texture_path.join("bevy.png")),
                 sprite: Sprite {
-                    //  flip_y: true,
-                    custom_size: Some(Vec2::new(OBSTACLE_WIDTH * 2.0, 300.0)),
-                    anchor: Anchor::BottomCenter,
-                    ..default()
+                    color,
+                    custom_size: Some(Vec2::new(
+                        config.sprite_size,
+                        config.sprite_size,
+                    )),
+                    ..Default::default()
                 },
-                // Bottom Collider Transform
-                transform: Transform::from_xyz(400.0, bottom_obstacle_y, 0.0),
-                texture: asset_server.load(texture_path.join("obstacle3.png")),
-                ..default()
-            })
-            // Top Obstacle Extension
-            .with_children(|children| {
-                // Middle Texture
-                children.spawn_bundle(SpriteBundle {
-                    sprite: Sprite {
-                        flip_y: true,
-                        custom_size: Some(Vec2::new(
-                            OBSTACLE_WIDTH * 1.6,
-                            OBSTACLE_WIDTH * 3.2,
-                        )),
-                        ..default()
-                    },
-                    texture: asset_server
-                        .load(texture_path.join("obstacle2.png")),
-                    transform: Transform::from_xyz(0.0, -70.0, 0.0),
-                    ..default()
-                });
-                // Bottom Texture
-                children.spawn_bundle(SpriteBundle {
-                    sprite: Sprite {
-                        flip_y: true,
-                        custom_size: Some(Vec2::new(
-                            OBSTACLE_WIDTH * 1.6,
-                            OBSTACLE_WIDTH * 3.2,
-                        )),
-                        ..default()
-                    },
-                    texture: asset_server
-                        .load(texture_path.join("obstacle2.png")),
-                    transform: Transform::from_xyz(0.0, -70.0 * 2.0, 0.0),
-                    ..default()
-                });
-            })
-            .insert(RigidBody::KinematicVelocityBased)
-            .insert(Collider::cuboid(OBSTACLE_WIDTH, 300.0))
-            .insert(Velocity {
-                linvel: Vec2::new(SCROLL_SPEED, 0.0),
-                angvel: 0.0,
+                transform: Transform::from_xyz(0.0, y, 0.0),
+                ..Default::default()
             })
-            .insert(ActiveCollisionTypes::all())
-            .insert(Obstacle);
+            .insert(RigidBody::Dynamic)
+            .insert(ExternalImpulse::default())
+            .insert(Velocity::default())
+            .insert(Collider::ball(config.sprite_size / 2.0))
+            .insert(ColliderMassProperties::Density(config.density))
+            .insert(GravityScale(config.gravity_scale))
+            // The birds fly through each other
+            .insert(CollisionGroups::new(Group::GROUP_2, !Group::GROUP_2))
+            .insert(Player(index))
+            .insert(ActionKey(button))
+            .insert(Score(0))
+            .insert(ActiveEvents::all());
+    }
+
+    // The demo flies behind the menu's text
+    if *mode == GameMode::Demo {
+        return;
+    }
+    for entity in &text_query {
+        commands.entity(entity).despawn();
     }
 }
 
-fn spawn_player(
-    mut commands: Commands,
-    text_query: Query<Entity, With<WelcomeText>>,
-    asset_server: Res<AssetServer>,
+/// The mass Rapier gives the player, for anything that has to fly like it.
+pub(crate) fn player_mass(
+    config: &GameConfig,
+    rapier_context: &RapierContext,
+) -> f32 {
+    let radius = config.sprite_size / 2.0 / rapier_context.physics_scale();
+    Collider::ball(radius)
+        .raw
+        .mass_properties(config.density)
+        .mass()
+}
+
+/// Holds on to the flaps pressed this frame until a tick flaps them.
+fn latch_flaps(
+    buttons: Buttons,
+    bindings: Res<InputBindings>,
+    mode: Res<GameMode>,
+    mut pressed: ResMut<PressedFlaps>,
+    player_info: Query<(&Player, &ActionKey), Without<Crashed>>,
 ) {
-    let texture_path = Path::new("textures");
-    // Spawn entity with `Player` struct as a component for access in movement query.
-    commands
-        .spawn()
-        .insert_bundle(SpriteBundle {
-            texture: asset_server.load(texture_path.join("bevy.png")),
-            sprite: Sprite {
-                custom_size: Some(Vec2::new(SPRITE_SIZE, SPRITE_SIZE)),
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(RigidBody::Dynamic)
-        .insert(ExternalImpulse::default())
-        .insert(Collider::ball(SPRITE_SIZE / 2.0))
-        .insert(ColliderMassProperties::Density(DENSITY))
-        .insert(GravityScale(GRAVITY_SCALE))
-        .insert(Player)
-        .insert(ActiveEvents::all());
+    // In versus each bird only answers to its own button
+    let shared = *mode != GameMode::Versus
+        && bindings.just_pressed(GameAction::Flap, &buttons);
 
-    for entity in &text_query {
-        commands.entity(entity).despawn();
+    for (player, action_key) in &player_info {
+        let up =
+            buttons.just_pressed(action_key.0) || (player.0 == 0 && shared);
+        if up && !pressed.0.contains(&player.0) {
+            pressed.0.push(player.0);
+        }
     }
 }
 
 fn player_movement(
-    keyboard_input: Res<Input<KeyCode>>,
-    action_key: Res<ActionKey>,
-    game_over: Res<GameOver>,
-    mut player_info: Query<&mut ExternalImpulse>,
+    config: Res<GameConfig>,
+    tick: Res<RunTick>,
+    playback: Option<ResMut<ReplayPlayback>>,
+    mut flap_request: ResMut<FlapRequest>,
+    mut pressed: ResMut<PressedFlaps>,
+    mut recorded_flaps: ResMut<RecordedFlaps>,
+    mut player_info: Query<(&Player, &mut ExternalImpulse), Without<Crashed>>,
 ) {
-    if game_over.0 {
-        return;
-    }
+    let requested = std::mem::take(&mut flap_request.0);
+    let pressed = std::mem::take(&mut pressed.0);
+    let replayed = playback.map(|mut playback| playback.flaps_at(tick.0));
 
-    // let mut rb_impulse = player_info.single_mut();
-    for mut player in &mut player_info {
-        let up = keyboard_input.just_pressed(KeyCode::W)
-            || keyboard_input.just_pressed(KeyCode::Up)
-            || keyboard_input.just_pressed(KeyCode::Space)
-            || keyboard_input.just_pressed(action_key.0);
+    for (player, mut impulse) in &mut player_info {
+        // Replays and agents fly the first bird
+        let first = player.0 == 0;
+        let up = match replayed {
+            Some(flaps) => first && flaps,
+            None => pressed.contains(&player.0) || (first && requested),
+        };
+        if up && first {
+            recorded_flaps.0.push(tick.0);
+        }
 
         if up {
-            player.impulse = Vec2::new(0.0, IMPULSE);
+            impulse.impulse = Vec2::new(0.0, config.impulse);
         } else {
-            player.impulse = Vec2::ZERO;
+            impulse.impulse = Vec2::ZERO;
         }
     }
 }
@@ -555,57 +1011,143 @@ fn player_movement(
 fn display_intersection_info(
     mut commands: Commands,
     mut score: ResMut<Score>,
-    game_over: Res<GameOver>,
-    mut text_query: Query<&mut Text, With<ScoreText>>,
+    mut text_query: Query<(&ScoreText, &mut Text, &mut Visibility)>,
+    mut player_query: Query<(&Player, &mut Score, Option<&Crashed>)>,
     obstacle_query: Query<(Entity, &Transform), With<InPlay>>,
+    config: Res<GameConfig>,
 ) {
-    if game_over.0 {
-        return;
-    }
-
     for (entity, transform) in &obstacle_query {
-        if transform.translation.x < (-SPRITE_SIZE) {
+        if transform.translation.x < (-config.sprite_size) {
             score.0 += 1;
             info!("Passed obstacle, score: {}", score.0);
+            for (_, mut bird_score, crashed) in &mut player_query {
+                if crashed.is_none() {
+                    bird_score.0 += 1;
+                }
+            }
 
             commands.entity(entity).remove::<InPlay>();
         }
     }
 
-    for mut text in &mut text_query {
-        text.sections[1].value = format!("{}", score.0);
+    // A column per bird in versus, otherwise just the one
+    let versus = player_query.iter().any(|(player, ..)| player.0 > 0);
+    for (column, mut text, mut visibility) in &mut text_query {
+        let bird_score = player_query
+            .iter()
+            .find(|(player, ..)| player.0 == column.0)
+            .map(|(_, bird_score, _)| bird_score.0);
+        visibility.is_visible = column.0 == 0 || bird_score.is_some();
+
+        let (label, color) = if versus {
+            (format!("P{}: ", column.0 + 1), versus::TINTS[column.0])
+        } else {
+            ("Score: ".to_string(), Color::WHITE)
+        };
+        text.sections[0].value = label;
+        text.sections[0].style.color = color;
+        text.sections[1].value = format!("{}", bird_score.unwrap_or(score.0));
     }
 }
 
+/// The birds that haven't crashed yet.
+type FlyingBirds = (With<Player>, Without<Crashed>);
+
 fn detect_collision(
-    mut game_over: ResMut<GameOver>,
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
     mut collision_event: EventReader<CollisionEvent>,
+    mut player_query: Query<(Entity, &mut Sprite), FlyingBirds>,
 ) {
+    let mut crashed = Vec::new();
     for event in collision_event.iter() {
         info!("Detected collision {:?}", event);
-        game_over.0 = true;
+        // Despawning a run emits `Stopped` events, only contacts kill
+        if let CollisionEvent::Started(a, b, _) = event {
+            crashed.extend(
+                [*a, *b]
+                    .into_iter()
+                    .filter(|&entity| player_query.contains(entity)),
+            );
+        }
+    }
+    if crashed.is_empty() {
+        return;
+    }
+
+    // The run goes on while any bird is still flying
+    if player_query
+        .iter()
+        .all(|(entity, _)| crashed.contains(&entity))
+    {
+        // Dying wins over a pause requested on the same frame
+        state.overwrite_push(AppState::GameOver).unwrap();
+        return;
+    }
+    for entity in crashed {
+        if let Ok((_, mut sprite)) = player_query.get_mut(entity) {
+            sprite.color = versus::CRASHED_TINT;
+        }
+        commands
+            .entity(entity)
+            .insert(Crashed)
+            .insert(CollisionGroups::new(Group::NONE, Group::NONE));
     }
 }
 
-fn detect_game_over(
+#[derive(Component)]
+struct GameOverText;
+
+fn spawn_game_over_text(
     mut commands: Commands,
-    game_over: Res<GameOver>,
     asset_server: Res<AssetServer>,
+    seed: Res<GameSeed>,
+    mode: Res<GameMode>,
+    player_query: Query<&Player, Without<Crashed>>,
 ) {
-    if game_over.0 {
-        let fonts_path = Path::new("fonts");
-        commands.spawn_bundle(
-            // Create a TextBundle that has a Text with a single section.
-            TextBundle::from_section(
-                // Accepts a `String` or any type that converts into a `String`, such as `&str`
-                "Oof, RIP.",
-                TextStyle {
-                    font: asset_server
-                        .load(fonts_path.join("FiraSans-Bold.ttf")),
-                    font_size: 100.0,
-                    color: Color::WHITE,
-                },
-            ) // Set the alignment of the Text
+    let fonts_path = Path::new("fonts");
+    // The last bird down is never marked as crashed, unless several went down
+    // together
+    let title = if *mode == GameMode::Versus {
+        match player_query.iter().collect::<Vec<_>>()[..] {
+            [winner] => format!("Player {} wins!\n", winner.0 + 1),
+            _ => "Draw!\n".to_string(),
+        }
+    } else {
+        "Oof, RIP.\n".to_string()
+    };
+    commands
+        .spawn_bundle(
+            // Create a TextBundle that has a Text with a list of sections.
+            TextBundle::from_sections([
+                TextSection::new(
+                    title,
+                    TextStyle {
+                        font: asset_server
+                            .load(fonts_path.join("FiraSans-Bold.ttf")),
+                        font_size: 100.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                TextSection::new(
+                    "Action key to retry\nEsc for the menu\n",
+                    TextStyle {
+                        font: asset_server
+                            .load(fonts_path.join("FiraSans-Bold.ttf")),
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                TextSection::new(
+                    format!("Seed: {}", seed.seed()),
+                    TextStyle {
+                        font: asset_server
+                            .load(fonts_path.join("FiraMono-Medium.ttf")),
+                        font_size: 30.0,
+                        color: Color::GOLD,
+                    },
+                ),
+            ]) // Set the alignment of the Text
             .with_text_alignment(TextAlignment::CENTER)
             // Set the style of the TextBundle itself.
             .with_style(Style {
@@ -618,49 +1160,286 @@ fn detect_game_over(
                 },
                 ..default()
             }),
-        );
+        )
+        .insert(GameOverText);
+}
+
+fn game_over_input(
+    buttons: Buttons,
+    bindings: Res<InputBindings>,
+    action_key_query: Query<&ActionKey, With<Player>>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if bindings.just_pressed(GameAction::Menu, &buttons) {
+        state.replace(AppState::Menu).unwrap();
+    } else if action_key_query
+        .iter()
+        .any(|action_key| buttons.just_pressed(action_key.0))
+        || bindings.just_pressed(GameAction::Restart, &buttons)
+    {
+        state.replace(AppState::Restarting).unwrap();
+    }
+}
+
+fn despawn_game_over_text(
+    mut commands: Commands,
+    text_query: Query<Entity, With<GameOverText>>,
+) {
+    for entity in &text_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Every entity that belongs to a single run.
+type RunEntities = Or<(With<Player>, With<Obstacle>, With<InPlay>)>;
+
+/// Clears out everything left behind by a run so the next one starts fresh.
+fn teardown_run(
+    mut commands: Commands,
+    mut score: ResMut<Score>,
+    mut timer: ResMut<SpawnNextObstacle>,
+    mut clock: ResMut<RunClock>,
+    mut seed: ResMut<GameSeed>,
+    config: Res<GameConfig>,
+    run_query: Query<Entity, RunEntities>,
+) {
+    for entity in &run_query {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    score.0 = 0;
+    *timer = SpawnNextObstacle::new(config.spawn_interval_secs);
+    clock.0.reset();
+    seed.reset();
+}
+
+fn restart_run(mut state: ResMut<State<AppState>>) {
+    // `set` refuses while the transition into this state is still underway
+    state.overwrite_set(AppState::InGame).unwrap();
+}
+
+const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
+
+#[derive(Component)]
+struct PauseOverlay;
+
+#[derive(Component, Clone, Copy)]
+enum PauseButton {
+    Resume,
+    Restart,
+    Controls,
+    Menu,
+}
+
+/// Puts the run on hold when asked to, or when the window loses focus.
+fn pause_game(
+    mut buttons: Buttons,
+    bindings: Res<InputBindings>,
+    action_key_query: Query<&ActionKey, With<Player>>,
+    mut focus_evr: EventReader<WindowFocused>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let lost_focus = focus_evr.iter().any(|ev| !ev.focused);
+    // The action buttons flap, whatever else they're bound to
+    let pause_button = bindings
+        .buttons(GameAction::Pause)
+        .iter()
+        .copied()
+        .filter(|&button| {
+            action_key_query
+                .iter()
+                .all(|action_key| action_key.0 != button)
+        })
+        .find(|&button| buttons.just_pressed(button));
+
+    if lost_focus || pause_button.is_some() {
+        // A collision on the same frame may already have queued a game over
+        if state.push(AppState::Paused).is_ok() {
+            info!("Pausing game");
+        }
+    }
+
+    // Stop the overlay from reading the same press as a resume
+    if let Some(button) = pause_button {
+        buttons.clear_just_pressed(button);
+    }
+}
+
+fn freeze_physics(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = false;
+}
+
+fn unfreeze_physics(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = true;
+}
+
+fn spawn_pause_overlay(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(Path::new("fonts").join("FiraSans-Bold.ttf"));
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                "Paused",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 100.0,
+                    color: Color::WHITE,
+                },
+            ));
+
+            for (button, label) in [
+                (PauseButton::Resume, "Resume"),
+                (PauseButton::Restart, "Restart"),
+                (PauseButton::Controls, "Controls"),
+                (PauseButton::Menu, "Quit to menu"),
+            ] {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(300.0), Val::Px(70.0)),
+                            margin: UiRect::all(Val::Px(10.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        color: NORMAL_BUTTON.into(),
+                        ..default()
+                    })
+                    .insert(button)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle::from_section(
+                            label,
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 40.0,
+                                color: Color::WHITE,
+                            },
+                        ));
+                    });
+            }
+        })
+        .insert(PauseOverlay);
+}
+
+fn pause_menu(
+    buttons: Buttons,
+    bindings: Res<InputBindings>,
+    mut state: ResMut<State<AppState>>,
+    mut button_query: Query<
+        (&Interaction, &PauseButton, &mut UiColor),
+        Changed<Interaction>,
+    >,
+) {
+    let mut choice = None;
+    if bindings.just_pressed(GameAction::Pause, &buttons) {
+        choice = Some(PauseButton::Resume);
+    }
+
+    for (interaction, button, mut color) in &mut button_query {
+        match *interaction {
+            Interaction::Clicked => choice = Some(*button),
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    }
+
+    match choice {
+        Some(PauseButton::Resume) => state.pop().unwrap(),
+        Some(PauseButton::Restart) => {
+            state.replace(AppState::Restarting).unwrap()
+        }
+        Some(PauseButton::Controls) => state.push(AppState::Controls).unwrap(),
+        Some(PauseButton::Menu) => state.replace(AppState::Menu).unwrap(),
+        None => {}
     }
 }
 
+fn despawn_pause_overlay(
+    mut commands: Commands,
+    overlay_query: Query<Entity, With<PauseOverlay>>,
+) {
+    for entity in &overlay_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// The score column of the player it's numbered for.
 #[derive(Component)]
-struct ScoreText;
+struct ScoreText(usize);
 #[derive(Component)]
 struct WelcomeText;
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     let fonts_path = Path::new("fonts");
-    // Score Text
+    // Score Text, a column for each player in versus
     commands
-        .spawn_bundle(
-            // Create a TextBundle that has a Text with a list of sections.
-            TextBundle::from_sections([
-                TextSection::new(
-                    "Score: ",
-                    TextStyle {
-                        font: asset_server
-                            .load(fonts_path.join("FiraSans-Bold.ttf")),
-                        font_size: 60.0,
-                        color: Color::WHITE,
-                    },
-                ),
-                TextSection::from_style(TextStyle {
-                    font: asset_server
-                        .load(fonts_path.join("FiraMono-Medium.ttf")),
-                    font_size: 60.0,
-                    color: Color::GOLD,
-                }),
-            ])
-            .with_style(Style {
+        .spawn_bundle(NodeBundle {
+            style: Style {
                 align_self: AlignSelf::FlexEnd,
                 ..default()
-            }),
-        )
-        .insert(ScoreText);
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            for column in 0..versus::PLAYERS {
+                parent
+                    .spawn_bundle(
+                        // Create a TextBundle that has a Text with a list of sections.
+                        TextBundle::from_sections([
+                            TextSection::new(
+                                "Score: ",
+                                TextStyle {
+                                    font: asset_server.load(
+                                        fonts_path.join("FiraSans-Bold.ttf"),
+                                    ),
+                                    font_size: 60.0,
+                                    color: Color::WHITE,
+                                },
+                            ),
+                            TextSection::from_style(TextStyle {
+                                font: asset_server.load(
+                                    fonts_path.join("FiraMono-Medium.ttf"),
+                                ),
+                                font_size: 60.0,
+                                color: Color::GOLD,
+                            }),
+                        ])
+                        .with_style(Style {
+                            margin: UiRect {
+                                right: Val::Px(40.0),
+                                ..default()
+                            },
+                            ..default()
+                        }),
+                    )
+                    // Only shown once there's a second bird
+                    .insert(Visibility {
+                        is_visible: column == 0,
+                    })
+                    .insert(ScoreText(column));
+            }
+        });
+}
 
+fn spawn_welcome_text(mut commands: Commands, asset_server: Res<AssetServer>) {
     // Start Text
     commands
         .spawn_bundle(
             TextBundle::from_section(
-                "Choose your \naction button!",
+                WELCOME_PROMPT,
                 TextStyle {
                     font: asset_server.load("fonts/FiraSans-Bold.ttf"),
                     font_size: 80.0,
@@ -679,4 +1458,4 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             }),
         )
         .insert(WelcomeText);
-}
\ No newline at end of file
+}
@@ -0,0 +1,438 @@
+//! The pipes the bird flies between, built the same way by everything that
+//! spawns them.
+//!
+//! Each pipe is a single entity carrying the collider, with its sprites as
+//! children: a cap on the end facing the gap, then as many segments as it
+//! takes to reach the other end. The sprites are fitted to the collider, not
+//! the other way around, so however a pipe is sized it looks as big as it
+//! is.
+//!
+//! Most pairs just scroll past, but further into a run some of them move: the
+//! whole pair bobs up and down, the gap opens and closes, or one pipe slides
+//! into place at the last moment. Which turns up is picked at random for
+//! every pair, with weights that shift along the difficulty curve. Like
+//! everything else in a run they move by the tick, so replays still play back
+//! exactly.
+
+use std::f32::consts::TAU;
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+use crate::difficulty::Difficulty;
+use crate::replay::TICK_SECS;
+use crate::{Gap, InPlay, Obstacle};
+
+/// Half the height of an obstacle collider, long enough for the far end to
+/// stay off screen wherever the gap moves.
+pub const OBSTACLE_HALF_HEIGHT: f32 = 400.0;
+
+/// How far a bobbing pair goes up and down.
+const BOB_AMPLITUDE: f32 = 50.0;
+const BOB_PERIOD_SECS: f32 = 4.0;
+/// How much wider than usual a breathing gap opens.
+const BREATHE_AMPLITUDE: f32 = 120.0;
+const BREATHE_PERIOD_SECS: f32 = 2.0;
+/// How far from its place a sliding pipe starts.
+const SLIDE_DISTANCE: f32 = 250.0;
+const SLIDE_SECS: f32 = 0.5;
+/// How long before the pair reaches the bird a sliding pipe is in place.
+const SLIDE_LEAD_SECS: f32 = 1.0;
+
+/// Size of `obstacle3.png`, in texture pixels.
+const CAP_TEXTURE_SIZE: Vec2 = Vec2::new(645.0, 1688.0);
+/// Size of `obstacle2.png`, in texture pixels. The cap's shaft is as wide, the
+/// rest of the cap is its lip.
+const SEGMENT_TEXTURE_SIZE: Vec2 = Vec2::new(522.0, 1023.0);
+
+/// How a pipe looks and moves.
+#[derive(Debug, Clone)]
+pub struct ObstacleStyle {
+    /// Half the width of the collider, which the pipe is drawn as wide as.
+    pub half_width: f32,
+    pub half_height: f32,
+    /// Horizontal speed, negative to scroll towards the bird.
+    pub speed: f32,
+    pub cap: Handle<Image>,
+    /// Repeated to fill the rest of the pipe.
+    pub segment: Handle<Image>,
+    pub motion: Motion,
+}
+
+impl ObstacleStyle {
+    /// The game's own pipes, as the config and difficulty curve have them.
+    pub fn new(
+        asset_server: &AssetServer,
+        config: &GameConfig,
+        difficulty: &Difficulty,
+    ) -> Self {
+        let texture_path = Path::new("textures");
+        ObstacleStyle {
+            half_width: config.obstacle_width,
+            half_height: OBSTACLE_HALF_HEIGHT,
+            speed: difficulty.scroll_speed,
+            cap: asset_server.load(texture_path.join("obstacle3.png")),
+            segment: asset_server.load(texture_path.join("obstacle2.png")),
+            motion: Motion::Still,
+        }
+    }
+}
+
+/// How likely each kind of pair is, relative to the others.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObstacleWeights {
+    pub still: f32,
+    pub bobbing: f32,
+    pub breathing: f32,
+    pub sliding: f32,
+}
+
+/// Only pairs that stand still.
+impl Default for ObstacleWeights {
+    fn default() -> Self {
+        ObstacleWeights {
+            still: 1.0,
+            bobbing: 0.0,
+            breathing: 0.0,
+            sliding: 0.0,
+        }
+    }
+}
+
+impl ObstacleWeights {
+    /// The weights `ramp` of the way from `self` to `other`.
+    pub fn lerp(&self, other: &Self, ramp: f32) -> Self {
+        let lerp = |from: f32, to: f32| from + (to - from) * ramp;
+        ObstacleWeights {
+            still: lerp(self.still, other.still),
+            bobbing: lerp(self.bobbing, other.bobbing),
+            breathing: lerp(self.breathing, other.breathing),
+            sliding: lerp(self.sliding, other.sliding),
+        }
+    }
+
+    pub(crate) fn fields(&self) -> [(&'static str, f32); 4] {
+        [
+            ("still", self.still),
+            ("bobbing", self.bobbing),
+            ("breathing", self.breathing),
+            ("sliding", self.sliding),
+        ]
+    }
+}
+
+/// How a pair of pipes moves up and down while it scrolls past.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motion {
+    Still,
+    /// The whole pair bobs up and down, starting `phase` radians into the
+    /// swing.
+    Bobbing {
+        phase: f32,
+    },
+    /// The gap opens wider and closes again, starting `phase` radians in.
+    Breathing {
+        phase: f32,
+    },
+    /// One pipe starts pulled away from the gap and slides into place after
+    /// `delay` ticks.
+    Sliding {
+        top: bool,
+        delay: u64,
+    },
+}
+
+impl Motion {
+    /// Picks how a new pair moves, as likely as `weights` say. `arrival` is
+    /// how many ticks the pair takes to reach the bird.
+    pub fn pick(
+        weights: &ObstacleWeights,
+        rng: &mut impl Rng,
+        arrival: u64,
+    ) -> Self {
+        let total: f32 =
+            weights.fields().iter().map(|(_, weight)| weight).sum();
+        if total <= 0.0 {
+            return Motion::Still;
+        }
+
+        let mut pick = rng.gen_range(0.0..total);
+        for (kind, weight) in weights.fields() {
+            if pick >= weight {
+                pick -= weight;
+                continue;
+            }
+            return match kind {
+                "bobbing" => Motion::Bobbing {
+                    phase: rng.gen_range(0.0..TAU),
+                },
+                "breathing" => Motion::Breathing {
+                    phase: rng.gen_range(0.0..TAU),
+                },
+                "sliding" => Motion::sliding(rng.gen_bool(0.5), arrival),
+                _ => Motion::Still,
+            };
+        }
+        Motion::Still
+    }
+
+    /// The top or bottom pipe of a pair `arrival` ticks away from the bird
+    /// sliding into place just in time.
+    pub fn sliding(top: bool, arrival: u64) -> Self {
+        Motion::Sliding {
+            top,
+            delay: arrival
+                .saturating_sub(secs_to_ticks(SLIDE_LEAD_SECS))
+                .saturating_sub(secs_to_ticks(SLIDE_SECS)),
+        }
+    }
+
+    /// How far the top and bottom pipes are moved up from where they'd stand
+    /// still, `age` ticks after the pair was spawned.
+    fn offsets(&self, age: u64) -> (f32, f32) {
+        let secs = age as f32 * TICK_SECS;
+        match *self {
+            Motion::Still => (0.0, 0.0),
+            Motion::Bobbing { phase } => {
+                let offset = BOB_AMPLITUDE
+                    * (TAU * secs / BOB_PERIOD_SECS + phase).sin();
+                (offset, offset)
+            }
+            Motion::Breathing { phase } => {
+                let opening = BREATHE_AMPLITUDE
+                    * (1.0 - (TAU * secs / BREATHE_PERIOD_SECS + phase).cos())
+                    / 2.0;
+                (opening / 2.0, -opening / 2.0)
+            }
+            Motion::Sliding { top, delay } => {
+                let progress = (age.saturating_sub(delay) as f32
+                    / secs_to_ticks(SLIDE_SECS) as f32)
+                    .min(1.0);
+                // Eases in and out of the slide
+                let eased = progress * progress * (3.0 - 2.0 * progress);
+                let pulled = SLIDE_DISTANCE * (1.0 - eased);
+                if top {
+                    (pulled, 0.0)
+                } else {
+                    (0.0, -pulled)
+                }
+            }
+        }
+    }
+}
+
+fn secs_to_ticks(secs: f32) -> u64 {
+    (secs / TICK_SECS).round() as u64
+}
+
+/// Moves a pair of pipes that doesn't stand still, kept on the top one.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct PairMotion {
+    pub motion: Motion,
+    /// The gap as it would be standing still.
+    pub still: Gap,
+    pub bottom: Entity,
+    /// Ticks since the pair was spawned.
+    pub age: u64,
+}
+
+impl PairMotion {
+    /// The gap `ticks` from now.
+    pub fn gap_in(&self, ticks: u64) -> Gap {
+        let (top, bottom) = self.motion.offsets(self.age + ticks);
+        Gap {
+            y: self.still.y + (top + bottom) / 2.0,
+            size: self.still.size + top - bottom,
+        }
+    }
+}
+
+/// What a pipe is drawn with. Its sprites are fitted to its collider whenever
+/// the collider changes.
+#[derive(Component, Debug, Clone)]
+pub struct Pipe {
+    pub cap: Handle<Image>,
+    pub segment: Handle<Image>,
+    /// Whether the gap is below the pipe, so the cap goes at the bottom.
+    pub gap_below: bool,
+}
+
+/// A single pipe. Its sprites are added as children once it's spawned.
+#[derive(Bundle)]
+pub struct ObstacleBundle {
+    #[bundle]
+    pub spatial: SpatialBundle,
+    pub rigid_body: RigidBody,
+    pub collider: Collider,
+    pub velocity: Velocity,
+    pub active_collision_types: ActiveCollisionTypes,
+    pub obstacle: Obstacle,
+    pub pipe: Pipe,
+}
+
+impl ObstacleBundle {
+    /// A pipe centred on `x`, `y`.
+    pub fn new(x: f32, y: f32, gap_below: bool, style: &ObstacleStyle) -> Self {
+        ObstacleBundle {
+            spatial: SpatialBundle::from(Transform::from_xyz(x, y, 0.0)),
+            rigid_body: RigidBody::KinematicVelocityBased,
+            collider: Collider::cuboid(style.half_width, style.half_height),
+            velocity: Velocity {
+                linvel: Vec2::new(style.speed, 0.0),
+                angvel: 0.0,
+            },
+            active_collision_types: ActiveCollisionTypes::all(),
+            obstacle: Obstacle,
+            pipe: Pipe {
+                cap: style.cap.clone(),
+                segment: style.segment.clone(),
+                gap_below,
+            },
+        }
+    }
+}
+
+/// Spawns the pipes above and below a gap of `gap_size` centred on `gap_y`,
+/// and returns them top first. The top one carries the [`Gap`] and scores.
+pub fn spawn_obstacle_pair(
+    commands: &mut Commands,
+    x: f32,
+    gap_y: f32,
+    gap_size: f32,
+    style: &ObstacleStyle,
+) -> (Entity, Entity) {
+    let offset = gap_size / 2.0 + style.half_height;
+    let (top_moved, bottom_moved) = style.motion.offsets(0);
+    let still = Gap {
+        y: gap_y,
+        size: gap_size,
+    };
+
+    let bottom = commands
+        .spawn_bundle(ObstacleBundle::new(
+            x,
+            gap_y - offset + bottom_moved,
+            false,
+            style,
+        ))
+        .id();
+    let mut top = commands.spawn_bundle(ObstacleBundle::new(
+        x,
+        gap_y + offset + top_moved,
+        true,
+        style,
+    ));
+    top.insert(InPlay);
+    if style.motion == Motion::Still {
+        top.insert(still);
+    } else {
+        let motion = PairMotion {
+            motion: style.motion,
+            still,
+            bottom,
+            age: 0,
+        };
+        top.insert(motion.gap_in(0)).insert(motion);
+    }
+
+    (top.id(), bottom)
+}
+
+/// Steers moving pairs to where they should be after this tick, once the
+/// bird has decided what to do.
+pub(crate) fn move_obstacles(
+    mut pair_query: Query<(Entity, &mut PairMotion, &mut Gap)>,
+    mut velocity_query: Query<&mut Velocity, With<Pipe>>,
+) {
+    for (top, mut pair, mut gap) in &mut pair_query {
+        let (top_moved, bottom_moved) = pair.motion.offsets(pair.age);
+        let (top_next, bottom_next) = pair.motion.offsets(pair.age + 1);
+        for (entity, moved) in [
+            (top, top_next - top_moved),
+            (pair.bottom, bottom_next - bottom_moved),
+        ] {
+            if let Ok(mut velocity) = velocity_query.get_mut(entity) {
+                velocity.linvel.y = moved / TICK_SECS;
+            }
+        }
+
+        pair.age += 1;
+        *gap = pair.gap_in(0);
+    }
+}
+
+/// Redraws every pipe whose collider is new or changed, so what's seen is
+/// always what the bird hits.
+pub(crate) fn fit_pipe_sprites(
+    mut commands: Commands,
+    pipe_query: Query<(Entity, &Pipe, &Collider), Changed<Collider>>,
+) {
+    for (entity, pipe, collider) in &pipe_query {
+        let half_extents = match collider.as_cuboid() {
+            Some(cuboid) => cuboid.half_extents(),
+            None => {
+                warn!("Pipe {:?} has a collider that isn't a box", entity);
+                continue;
+            }
+        };
+        let mut pipe_commands = commands.entity(entity);
+        pipe_commands.despawn_descendants();
+        pipe_commands.with_children(|children| {
+            spawn_sprites(children, pipe, half_extents)
+        });
+    }
+}
+
+/// Draws a pipe over a box collider, from the cap on the end facing the gap
+/// to the far end. Everything but the cap's lip is as wide as the collider.
+fn spawn_sprites(children: &mut ChildBuilder, pipe: &Pipe, half_extents: Vec2) {
+    let width = half_extents.x * 2.0;
+    let length = half_extents.y * 2.0;
+    // Local y of a point `from_gap` along the pipe
+    let facing = if pipe.gap_below { -1.0 } else { 1.0 };
+    let along = |from_gap: f32| facing * (half_extents.y - from_gap);
+    // The textures are drawn for pipes coming up from the ground
+    let flip_y = pipe.gap_below;
+
+    // Scaled so the cap's shaft is as wide as the collider
+    let scale = width / SEGMENT_TEXTURE_SIZE.x;
+    let cap = CAP_TEXTURE_SIZE * scale;
+    let cap_height = cap.y.min(length);
+    children.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            flip_y,
+            custom_size: Some(Vec2::new(cap.x, cap_height)),
+            ..default()
+        },
+        texture: pipe.cap.clone(),
+        transform: Transform::from_xyz(0.0, along(cap_height / 2.0), 0.0),
+        ..default()
+    });
+
+    let segment_height = SEGMENT_TEXTURE_SIZE.y * scale;
+    let mut drawn = cap_height;
+    while drawn < length {
+        // The last one is squashed to end with the collider
+        let height = segment_height.min(length - drawn);
+        children.spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                flip_y,
+                custom_size: Some(Vec2::new(width, height)),
+                ..default()
+            },
+            texture: pipe.segment.clone(),
+            transform: Transform::from_xyz(
+                0.0,
+                along(drawn + height / 2.0),
+                0.0,
+            ),
+            ..default()
+        });
+        drawn += height;
+    }
+}
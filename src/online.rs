@@ -0,0 +1,340 @@
+//! Racing someone on another machine, through the relay server in `relay/`.
+//!
+//! Both players join the same room on the relay, which picks the course for
+//! them. Every tick of a run each game sends where its bird is, and draws the
+//! opponent's as a ghost at the same point of the course. See the relay for
+//! how clients are paired.
+//!
+//! The game only ever talks to a [`RelayConnection`], each launcher decides
+//! how it reaches the relay.
+
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+use crate::replay::{RecordedFlaps, RunTick};
+use crate::seed::GameSeed;
+use crate::{versus, AppState, GameMode, Player, Score};
+
+/// Everything sent to and from the relay, one JSON message at a time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Message {
+    /// Asks the relay for an opponent in `room`.
+    Join { room: String },
+    /// From the relay once the opponent has joined.
+    Start { seed: u64, player: usize },
+    /// From the relay when the opponent disconnects.
+    Left,
+    /// Where the sender's bird is after `tick`, and whether it flapped on it.
+    Bird {
+        tick: u64,
+        y: f32,
+        velocity: f32,
+        flapped: bool,
+    },
+    /// The sender's bird crashed after `tick`.
+    Crashed { tick: u64, score: u128 },
+}
+
+/// A way to reach the relay that carries whole messages.
+pub trait RelayConnection: Send + Sync + 'static {
+    fn send(&self, message: &str) -> io::Result<()>;
+
+    /// The messages that arrived since the last call, oldest first.
+    fn receive(&self) -> Vec<String>;
+}
+
+/// One message per line over TCP.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct TcpRelay {
+    stream: std::sync::Mutex<std::net::TcpStream>,
+    received: std::sync::Mutex<std::sync::mpsc::Receiver<String>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TcpRelay {
+    pub fn connect(addr: impl std::net::ToSocketAddrs) -> io::Result<Self> {
+        use std::io::BufRead;
+
+        let stream = std::net::TcpStream::connect(addr)?;
+        let reader = std::io::BufReader::new(stream.try_clone()?);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for line in reader.lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        warn!("Lost the relay: {}", err);
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(TcpRelay {
+            stream: std::sync::Mutex::new(stream),
+            received: std::sync::Mutex::new(receiver),
+        })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl RelayConnection for TcpRelay {
+    fn send(&self, message: &str) -> io::Result<()> {
+        use std::io::Write;
+
+        let mut stream = self.stream.lock().unwrap();
+        stream.write_all(message.as_bytes())?;
+        stream.write_all(b"\n")
+    }
+
+    fn receive(&self) -> Vec<String> {
+        self.received.lock().unwrap().try_iter().collect()
+    }
+}
+
+/// The connection to the relay and what it has said so far.
+pub struct Relay {
+    connection: Box<dyn RelayConnection>,
+    /// This game's player number, once the relay found an opponent.
+    player: Option<usize>,
+    /// Whether the crash of the last run was sent already.
+    sent_crash: bool,
+}
+
+impl Relay {
+    /// Whether there's an opponent to race.
+    pub fn matched(&self) -> bool {
+        self.player.is_some()
+    }
+
+    fn send(&self, message: &Message) {
+        let result = serde_json::to_string(message)
+            .map_err(io::Error::from)
+            .and_then(|line| self.connection.send(&line));
+        if let Err(err) = result {
+            warn!("Unable to reach the relay: {}", err);
+        }
+    }
+}
+
+/// Where the opponent's bird was after a tick.
+#[derive(Debug, Clone, Copy)]
+struct BirdState {
+    tick: u64,
+    y: f32,
+}
+
+/// The opponent's current run, as far as it has been received.
+#[derive(Default)]
+pub struct OpponentRun {
+    states: VecDeque<BirdState>,
+    /// The tick the run ended on and its score.
+    crashed: Option<(u64, u128)>,
+}
+
+impl OpponentRun {
+    /// Where the opponent's bird was after `tick`, or the latest received if
+    /// they are behind.
+    fn y_at(&self, tick: u64) -> Option<f32> {
+        self.states
+            .iter()
+            .rev()
+            .find(|state| state.tick <= tick)
+            .map(|state| state.y)
+    }
+}
+
+/// The opponent's bird, drawn where theirs was at the same point of the
+/// course.
+#[derive(Component)]
+pub struct OpponentBird;
+
+/// Plays online through `connection`, against whoever joins `room`.
+pub fn connect(app: &mut App, connection: impl RelayConnection, room: &str) {
+    let relay = Relay {
+        connection: Box::new(connection),
+        player: None,
+        sent_crash: false,
+    };
+    relay.send(&Message::Join {
+        room: room.to_string(),
+    });
+    info!("Looking for an opponent in room {:?}", room);
+
+    app.insert_resource(GameMode::Online).insert_resource(relay);
+}
+
+/// Reads whatever the relay passed on since the last frame.
+pub(crate) fn receive_messages(
+    relay: Option<ResMut<Relay>>,
+    mut opponent: ResMut<OpponentRun>,
+    mut seed: ResMut<GameSeed>,
+) {
+    let mut relay = match relay {
+        Some(relay) => relay,
+        None => return,
+    };
+
+    for line in relay.connection.receive() {
+        let message = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(err) => {
+                warn!("Ignoring relay message {:?}: {}", line, err);
+                continue;
+            }
+        };
+
+        match message {
+            Message::Start {
+                seed: course,
+                player,
+            } => {
+                info!("Racing an opponent as player {}", player + 1);
+                *seed = GameSeed::fixed(course);
+                relay.player = Some(player);
+                *opponent = OpponentRun::default();
+            }
+            Message::Left => {
+                info!("The opponent left");
+                relay.player = None;
+                *opponent = OpponentRun::default();
+            }
+            Message::Bird { tick, y, .. } => {
+                // Ticks start over with every run
+                if opponent.states.back().is_some_and(|last| last.tick >= tick)
+                {
+                    *opponent = OpponentRun::default();
+                }
+                opponent.states.push_back(BirdState { tick, y });
+            }
+            Message::Crashed { tick, score } => {
+                info!("The opponent crashed with a score of {}", score);
+                opponent.crashed = Some((tick, score));
+            }
+            // Only ever sent to the relay
+            Message::Join { .. } => {}
+        }
+    }
+}
+
+/// Sends the opponent where the bird is once the tick has been simulated.
+pub(crate) fn send_bird(
+    relay: Option<ResMut<Relay>>,
+    state: Res<State<AppState>>,
+    tick: Res<RunTick>,
+    score: Res<Score>,
+    flaps: Res<RecordedFlaps>,
+    player_query: Query<(&Player, &Transform, &Velocity)>,
+) {
+    let mut relay = match relay {
+        Some(relay) if relay.matched() => relay,
+        _ => return,
+    };
+
+    match state.current() {
+        AppState::InGame => relay.sent_crash = false,
+        AppState::GameOver if !relay.sent_crash => {
+            relay.sent_crash = true;
+            relay.send(&Message::Crashed {
+                tick: tick.0,
+                score: score.0,
+            });
+            return;
+        }
+        _ => return,
+    }
+
+    for (player, transform, velocity) in &player_query {
+        if player.0 != 0 {
+            continue;
+        }
+        relay.send(&Message::Bird {
+            tick: tick.0,
+            y: transform.translation.y,
+            velocity: velocity.linvel.y,
+            // The tick has moved on since the flap was recorded
+            flapped: tick
+                .0
+                .checked_sub(1)
+                .is_some_and(|flap_tick| flaps.0.last() == Some(&flap_tick)),
+        });
+    }
+}
+
+pub(crate) fn spawn_opponent(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    relay: Option<Res<Relay>>,
+) {
+    let player = match relay.and_then(|relay| relay.player) {
+        Some(player) => player,
+        None => return,
+    };
+
+    let mut color = versus::TINTS[1 - player];
+    color.set_a(0.6);
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: asset_server.load(Path::new("textures").join("bevy.png")),
+            sprite: Sprite {
+                color,
+                custom_size: Some(Vec2::new(
+                    config.sprite_size,
+                    config.sprite_size,
+                )),
+                ..default()
+            },
+            // In front of the background, like the ghost
+            transform: Transform::from_xyz(0.0, 0.0, 0.5),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(OpponentBird);
+}
+
+/// Shows the opponent where they were at the same point of the course.
+pub(crate) fn move_opponent(
+    tick: Res<RunTick>,
+    opponent: Res<OpponentRun>,
+    mut bird_query: Query<
+        (&mut Transform, &mut Visibility, &mut Sprite),
+        With<OpponentBird>,
+    >,
+) {
+    for (mut transform, mut visibility, mut sprite) in &mut bird_query {
+        let y = opponent.y_at(tick.0);
+        visibility.is_visible = y.is_some();
+        if let Some(y) = y {
+            transform.translation.y = y;
+        }
+
+        if opponent
+            .crashed
+            .is_some_and(|(crash_tick, _)| crash_tick <= tick.0)
+        {
+            sprite.color = versus::CRASHED_TINT;
+        }
+    }
+}
+
+pub(crate) fn despawn_opponent(
+    mut commands: Commands,
+    bird_query: Query<Entity, With<OpponentBird>>,
+) {
+    for entity in &bird_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,198 @@
+//! Recording the flaps of a run so it can be played back exactly.
+//!
+//! The physics and every gameplay timer advance by [`TICK_SECS`] each tick,
+//! so a run is decided entirely by its seed, its config and the ticks the
+//! player flapped on.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+use crate::controls::ActionButton;
+use crate::seed::GameSeed;
+use crate::storage::GameStorage;
+use crate::{ActionKey, AppState, GameMode, Score};
+
+/// How far the game moves on every tick, in seconds.
+pub const TICK_SECS: f32 = 1.0 / 60.0;
+
+/// Where the last run played is kept, for attaching to bug reports.
+const STORAGE_KEY: &str = "last_replay";
+
+/// How long a replay may go on past its last flap before [`simulate`] gives
+/// up on it. A bird that stops flapping hits the ground well within this.
+const FALL_TICKS: u64 = 600;
+
+/// Everything needed to play a run again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    /// The [`config_hash`] of the config the run was played with.
+    pub config_hash: u64,
+    /// The ticks the player flapped on, in order.
+    pub flaps: Vec<u64>,
+}
+
+impl Replay {
+    /// Reads a replay saved by the game.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let saved = fs::read_to_string(path)?;
+        ron::from_str(&saved)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Tells configs apart, so a replay played with different settings than it
+/// was recorded with can be flagged.
+pub fn config_hash(config: &GameConfig) -> u64 {
+    // FNV-1a, which unlike the std hasher is the same on every build
+    let saved = ron::to_string(config).unwrap_or_default();
+    saved.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Plays `replay` instead of reading the keyboard, on every run.
+pub fn play_replay(app: &mut App, replay: Replay) {
+    app.insert_resource(GameMode::Replay)
+        .insert_resource(GameSeed::fixed(replay.seed))
+        .insert_resource(ReplayPlayback::new(replay));
+}
+
+/// Plays `replay` on a headless game with `config` and returns the score the
+/// run ends on, or `None` if the bird is somehow still flying long after its
+/// last flap.
+pub fn simulate(replay: &Replay, config: GameConfig) -> Option<u128> {
+    let give_up = replay.flaps.last().map_or(0, |&last| last) + FALL_TICKS;
+
+    let mut app = crate::headless_app();
+    app.insert_resource(config)
+        // Never pressed, the replay does the flapping
+        .insert_resource(ActionKey(ActionButton::Key(KeyCode::Space)));
+    play_replay(&mut app, replay.clone());
+    app.update();
+    app.world
+        .resource_mut::<State<AppState>>()
+        .set(AppState::InGame)
+        .unwrap();
+
+    loop {
+        app.update();
+        if *app.world.resource::<State<AppState>>().current()
+            == AppState::GameOver
+        {
+            return Some(app.world.resource::<Score>().0);
+        }
+        if app.world.resource::<RunTick>().0 > give_up {
+            return None;
+        }
+    }
+}
+
+/// The replay being watched and how far into it the run is.
+pub(crate) struct ReplayPlayback {
+    replay: Replay,
+    next: usize,
+}
+
+impl ReplayPlayback {
+    pub(crate) fn new(replay: Replay) -> Self {
+        ReplayPlayback { replay, next: 0 }
+    }
+
+    /// Whether the recorded player flapped on `tick`.
+    pub(crate) fn flaps_at(&mut self, tick: u64) -> bool {
+        let flaps = &self.replay.flaps;
+        while flaps.get(self.next).is_some_and(|&flap| flap < tick) {
+            self.next += 1;
+        }
+
+        if flaps.get(self.next) == Some(&tick) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// How many ticks the current run has been going.
+#[derive(Default)]
+pub(crate) struct RunTick(pub(crate) u64);
+
+/// The flaps of the current run so far.
+#[derive(Default)]
+pub(crate) struct RecordedFlaps(pub(crate) Vec<u64>);
+
+impl RecordedFlaps {
+    /// The current run as a replay.
+    pub(crate) fn to_replay(
+        &self,
+        seed: &GameSeed,
+        config: &GameConfig,
+    ) -> Replay {
+        Replay {
+            seed: seed.seed(),
+            config_hash: config_hash(config),
+            flaps: self.0.clone(),
+        }
+    }
+}
+
+pub(crate) fn start_recording(
+    mut tick: ResMut<RunTick>,
+    mut flaps: ResMut<RecordedFlaps>,
+    playback: Option<ResMut<ReplayPlayback>>,
+    config: Res<GameConfig>,
+) {
+    tick.0 = 0;
+    flaps.0.clear();
+
+    if let Some(mut playback) = playback {
+        playback.next = 0;
+        if playback.replay.config_hash != config_hash(&config) {
+            warn!(
+                "Replay was recorded with a different game config, it may \
+                 not play back the same"
+            );
+        }
+    }
+}
+
+pub(crate) fn advance_tick(mut tick: ResMut<RunTick>) {
+    tick.0 += 1;
+}
+
+pub(crate) fn save_replay(
+    mode: Res<GameMode>,
+    seed: Res<GameSeed>,
+    config: Res<GameConfig>,
+    flaps: Res<RecordedFlaps>,
+    storage: Res<GameStorage>,
+) {
+    // Only the first bird's flaps are recorded, and a replay only knows its
+    // seed, not the course it was flown on
+    if matches!(
+        *mode,
+        GameMode::Replay | GameMode::Demo | GameMode::Versus | GameMode::Course
+    ) {
+        return;
+    }
+
+    let replay = flaps.to_replay(&seed, &config);
+    let result = ron::to_string(&replay)
+        .map_err(|err| err.to_string())
+        .and_then(|saved| {
+            storage
+                .save(STORAGE_KEY, &saved)
+                .map_err(|err| err.to_string())
+        });
+
+    if let Err(err) = result {
+        warn!("Unable to save replay: {}", err);
+    }
+}
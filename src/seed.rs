@@ -0,0 +1,64 @@
+//! The seed every random decision in a run is drawn from, so a course can be
+//! played again.
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// The seed of the current course and the random numbers drawn from it.
+///
+/// A fixed seed replays the same course on every run, otherwise a fresh seed
+/// is rolled each time a run ends.
+pub struct GameSeed {
+    seed: u64,
+    fixed: bool,
+    rng: ChaCha8Rng,
+}
+
+impl GameSeed {
+    /// Plays the course for `seed` on every run.
+    pub fn fixed(seed: u64) -> Self {
+        GameSeed {
+            seed,
+            fixed: true,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Plays a different course on every run.
+    pub fn random() -> Self {
+        let seed = rand::thread_rng().gen();
+        GameSeed {
+            seed,
+            fixed: false,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Whether every run plays the same course.
+    pub fn is_fixed(&self) -> bool {
+        self.fixed
+    }
+
+    pub fn rng(&mut self) -> &mut ChaCha8Rng {
+        &mut self.rng
+    }
+
+    /// Gets ready for the next run.
+    pub(crate) fn reset(&mut self) {
+        *self = if self.fixed {
+            GameSeed::fixed(self.seed)
+        } else {
+            GameSeed::random()
+        };
+    }
+}
+
+impl Default for GameSeed {
+    fn default() -> Self {
+        GameSeed::random()
+    }
+}
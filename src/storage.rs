@@ -0,0 +1,95 @@
+//! Key-value persistence for everything the game keeps between sessions.
+//!
+//! The game only ever talks to [`GameStorage`], each launcher decides which
+//! backend sits behind it.
+
+use std::collections::HashMap;
+use std::io;
+use std::ops::Deref;
+use std::sync::Mutex;
+
+/// A place to keep small, named blobs of text.
+pub trait Storage: Send + Sync + 'static {
+    /// Returns the value stored under `key`, if there is one.
+    fn load(&self, key: &str) -> Option<String>;
+
+    /// Stores `value` under `key`, replacing whatever was there before.
+    fn save(&self, key: &str, value: &str) -> io::Result<()>;
+}
+
+/// The storage backend used by the running game.
+pub struct GameStorage(Box<dyn Storage>);
+
+impl GameStorage {
+    pub fn new(storage: impl Storage) -> Self {
+        GameStorage(Box::new(storage))
+    }
+}
+
+/// Nothing is persisted unless a launcher provides a real backend.
+impl Default for GameStorage {
+    fn default() -> Self {
+        GameStorage::new(MemoryStorage::default())
+    }
+}
+
+impl Deref for GameStorage {
+    type Target = dyn Storage;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+/// Keeps everything in memory, forgotten once the process exits.
+#[derive(Default)]
+pub struct MemoryStorage(Mutex<HashMap<String, String>>);
+
+impl Storage for MemoryStorage {
+    fn load(&self, key: &str) -> Option<String> {
+        self.0.lock().unwrap().get(key).cloned()
+    }
+
+    fn save(&self, key: &str, value: &str) -> io::Result<()> {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+/// Stores every key as a `<key>.ron` file inside a directory.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileStorage {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStorage {
+    pub fn new(dir: impl Into<std::path::PathBuf>) -> Self {
+        FileStorage { dir: dir.into() }
+    }
+
+    /// Uses the platform data directory, e.g. `~/.local/share/bevy_bird`.
+    pub fn in_data_dir() -> Option<Self> {
+        directories::ProjectDirs::from("", "", "bevy_bird")
+            .map(|dirs| FileStorage::new(dirs.data_dir()))
+    }
+
+    fn path(&self, key: &str) -> std::path::PathBuf {
+        self.dir.join(key).with_extension("ron")
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Storage for FileStorage {
+    fn load(&self, key: &str) -> Option<String> {
+        std::fs::read_to_string(self.path(key)).ok()
+    }
+
+    fn save(&self, key: &str, value: &str) -> io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.path(key), value)
+    }
+}
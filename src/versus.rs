@@ -0,0 +1,38 @@
+//! Two players on one keyboard, or any mix of devices, racing their own birds
+//! through the same course. A bird that crashes drops out, and the run goes
+//! on until the last one is down.
+
+use bevy::prelude::*;
+
+use crate::config::GameConfig;
+use crate::controls::ActionButton;
+
+/// How many birds fly in a versus run.
+pub const PLAYERS: usize = 2;
+
+/// Tints telling the birds apart, and the colour of each one's score.
+pub(crate) const TINTS: [Color; PLAYERS] =
+    [Color::rgb(0.5, 0.8, 1.0), Color::rgb(1.0, 0.6, 0.3)];
+
+/// What a bird fades to once it's out.
+pub(crate) const CRASHED_TINT: Color = Color::rgba(0.5, 0.5, 0.5, 0.6);
+
+/// The button player two picked on the menu. Player one's is the
+/// [`ActionKey`](crate::ActionKey).
+pub struct SecondActionKey(pub ActionButton);
+
+/// A bird that has crashed while another is still flying. It no longer
+/// touches anything and is left to fall.
+#[derive(Component)]
+pub struct Crashed;
+
+/// Where player `index`'s bird starts, one above the middle and the other
+/// below, so they don't sit on top of each other.
+pub(crate) fn lane_y(index: usize, config: &GameConfig) -> f32 {
+    let offset = config.sprite_size * 0.6;
+    if index == 0 {
+        offset
+    } else {
+        -offset
+    }
+}
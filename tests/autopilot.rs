@@ -0,0 +1,34 @@
+use bevy::prelude::*;
+
+use bevy_bird::autopilot::Autopilot;
+use bevy_bird::controls::ActionButton;
+use bevy_bird::seed::GameSeed;
+use bevy_bird::{headless_app, ActionKey, AppState, Score};
+
+/// Past the point where the default difficulty curve is at its hardest.
+const OBSTACLES: u128 = 30;
+const SEED: u64 = 7;
+
+#[test]
+fn autopilot_survives_the_hardest_difficulty() {
+    let mut app = headless_app();
+    app.insert_resource(GameSeed::fixed(SEED))
+        .insert_resource(ActionKey(ActionButton::Key(KeyCode::Space)))
+        .insert_resource(Autopilot);
+    app.update();
+    app.world
+        .resource_mut::<State<AppState>>()
+        .set(AppState::InGame)
+        .unwrap();
+
+    while app.world.resource::<Score>().0 < OBSTACLES {
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<AppState>>().current(),
+            AppState::InGame,
+            "bot died after {} obstacles with seed {}",
+            app.world.resource::<Score>().0,
+            SEED,
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde_json::Value;
+
+use bevy_bird::{bridge, headless_app};
+
+/// Steps the game until the agent gets a message.
+fn next_message(app: &mut App, reader: &mut BufReader<TcpStream>) -> Value {
+    let mut line = String::new();
+    for _ in 0..100 {
+        app.update();
+        if reader.read_line(&mut line).is_ok() && line.ends_with('\n') {
+            return serde_json::from_str(&line).unwrap();
+        }
+    }
+    panic!("no message from the game, got {:?}", line);
+}
+
+#[test]
+fn scripted_agent_plays_a_run() {
+    let mut app = headless_app();
+    let addr = bridge::serve(&mut app, "127.0.0.1:0").unwrap();
+    app.update();
+
+    let mut agent = TcpStream::connect(addr).unwrap();
+    agent
+        .set_read_timeout(Some(Duration::from_millis(20)))
+        .unwrap();
+    let mut reader = BufReader::new(agent.try_clone().unwrap());
+    writeln!(agent, "\"restart\"").unwrap();
+
+    let first = next_message(&mut app, &mut reader);
+    assert_eq!(first["done"], false);
+    assert!(first["obstacles"][0]["distance"].as_f64().unwrap() > 0.0);
+
+    // Flapping lifts the bird
+    writeln!(agent, "\"flap\"").unwrap();
+    let mut message = next_message(&mut app, &mut reader);
+    for _ in 0..5 {
+        if message["bird_velocity"].as_f64().unwrap() > 0.0 {
+            break;
+        }
+        message = next_message(&mut app, &mut reader);
+    }
+    assert!(message["bird_velocity"].as_f64().unwrap() > 0.0);
+
+    // Then it falls to its death
+    while message["done"] == false {
+        message = next_message(&mut app, &mut reader);
+    }
+    assert!(
+        message["tick"].as_u64().unwrap() > first["tick"].as_u64().unwrap()
+    );
+}
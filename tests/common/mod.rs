@@ -0,0 +1,53 @@
+//! Fixtures shared by the integration tests.
+
+// Every test file pulls in the whole module but only uses some of it
+#![allow(dead_code)]
+
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+
+use bevy_bird::controls::ActionButton;
+use bevy_bird::{headless_app, ActionKey, AppState};
+
+pub fn current_state(app: &App) -> AppState {
+    app.world.resource::<State<AppState>>().current().clone()
+}
+
+pub fn send_key(app: &mut App, key_code: KeyCode, state: ButtonState) {
+    app.world.send_event(KeyboardInput {
+        scan_code: 0,
+        key_code: Some(key_code),
+        state,
+    });
+}
+
+/// Presses `key_code` for a frame, then releases it for another.
+pub fn tap_key(app: &mut App, key_code: KeyCode) {
+    for state in [ButtonState::Pressed, ButtonState::Released] {
+        send_key(app, key_code, state);
+        app.update();
+    }
+}
+
+/// Steps a fresh game past the menu, flapping with `key_code`.
+pub fn start_run(key_code: KeyCode) -> App {
+    let mut app = headless_app();
+    app.update();
+    send_key(&mut app, key_code, ButtonState::Pressed);
+    send_key(&mut app, key_code, ButtonState::Released);
+    app.update();
+    assert_eq!(current_state(&app), AppState::InGame);
+    app
+}
+
+/// Puts `app` straight into a run that flaps with Space, without going
+/// through the menu.
+pub fn skip_menu(app: &mut App) {
+    app.insert_resource(ActionKey(ActionButton::Key(KeyCode::Space)));
+    app.update();
+    app.world
+        .resource_mut::<State<AppState>>()
+        .set(AppState::InGame)
+        .unwrap();
+}
@@ -0,0 +1,45 @@
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::Velocity;
+
+use bevy_bird::controls::{ActionButton, GameAction, InputBindings};
+use bevy_bird::storage::GameStorage;
+use bevy_bird::{headless_app, AppState, Player};
+
+mod common;
+use common::{current_state, send_key, tap_key};
+
+#[test]
+fn rebound_flap_button_is_saved_and_flaps() {
+    let mut app = headless_app();
+    app.update();
+
+    tap_key(&mut app, KeyCode::F1);
+    assert_eq!(current_state(&app), AppState::Controls);
+
+    // Flap is the first row
+    tap_key(&mut app, KeyCode::Return);
+    tap_key(&mut app, KeyCode::K);
+    tap_key(&mut app, KeyCode::Escape);
+    assert_eq!(current_state(&app), AppState::Menu);
+
+    let bindings = app.world.resource::<InputBindings>();
+    assert!(bindings
+        .buttons(GameAction::Flap)
+        .contains(&ActionButton::Key(KeyCode::K)));
+    let saved = app.world.resource::<GameStorage>().load("bindings");
+    assert!(saved.is_some_and(|saved| saved.contains("K")));
+
+    tap_key(&mut app, KeyCode::J);
+    assert_eq!(current_state(&app), AppState::InGame);
+    send_key(&mut app, KeyCode::K, ButtonState::Pressed);
+    app.update();
+
+    let velocity = app
+        .world
+        .query_filtered::<&Velocity, With<Player>>()
+        .single(&app.world)
+        .linvel
+        .y;
+    assert!(velocity > 0.0, "bird moving at {} after K", velocity);
+}
@@ -0,0 +1,137 @@
+use bevy::prelude::*;
+
+use bevy_bird::autopilot::Autopilot;
+use bevy_bird::controls::ActionButton;
+use bevy_bird::course::{self, Course};
+use bevy_bird::obstacle::PairMotion;
+use bevy_bird::{headless_app, ActionKey, AppState, Gap};
+
+const TUTORIAL: &str = "assets/courses/tutorial.course.ron";
+
+/// Positions are floats moved by the physics, a little slack is fine.
+const EPSILON: f32 = 0.01;
+
+/// A pair as first seen on the course.
+#[derive(Clone, Copy)]
+struct Seen {
+    entity: Entity,
+    x: f32,
+    gap: Gap,
+}
+
+#[test]
+fn courses_play_out_pair_by_pair_then_go_random() {
+    let course = Course::load(TUTORIAL).unwrap();
+    let mut app = headless_app();
+    app.insert_resource(ActionKey(ActionButton::Key(KeyCode::Space)))
+        .insert_resource(Autopilot);
+    course::play_course(&mut app, course.clone());
+    app.update();
+    app.world
+        .resource_mut::<State<AppState>>()
+        .set(AppState::InGame)
+        .unwrap();
+
+    let mut pairs = app
+        .world
+        .query::<(Entity, &Transform, &Gap, Option<&PairMotion>)>();
+    let mut seen: Vec<Seen> = Vec::new();
+    // Until the random generator has taken over
+    while seen.len() <= course.pairs.len() {
+        app.update();
+        assert_eq!(
+            app.world.resource::<State<AppState>>().current(),
+            &AppState::InGame,
+            "the autopilot crashed on the course"
+        );
+
+        let in_play: Vec<Seen> = pairs
+            .iter(&app.world)
+            .map(|(entity, transform, gap, motion)| Seen {
+                entity,
+                x: transform.translation.x,
+                gap: motion.map_or(*gap, |motion| motion.still),
+            })
+            .collect();
+        for pair in in_play.iter() {
+            if seen.iter().any(|seen| seen.entity == pair.entity) {
+                continue;
+            }
+            let next = seen.len();
+            if let (Some(previous), Some(course_pair)) =
+                (seen.last(), course.pairs.get(next))
+            {
+                let previous = in_play
+                    .iter()
+                    .find(|pair| pair.entity == previous.entity)
+                    .unwrap();
+                let spacing = pair.x - previous.x;
+                assert!(
+                    (spacing - course_pair.spacing).abs() < EPSILON,
+                    "pair {} came {} behind the last one, not {}",
+                    next,
+                    spacing,
+                    course_pair.spacing
+                );
+            }
+            seen.push(Seen { ..*pair });
+        }
+    }
+
+    for (pair, seen) in course.pairs.iter().zip(&seen) {
+        assert_eq!(
+            seen.gap,
+            Gap {
+                y: pair.gap_y,
+                size: pair.gap_size,
+            }
+        );
+    }
+}
+
+#[test]
+fn malformed_courses_say_where_and_what() {
+    for (course, line, column, message) in [
+        (
+            "(pairs: [\n    (spacing: 0.0, gap_y: 0.0, gap_size: 400.0),\n    \
+             (spacing: 300.0, gap_y: 0.0, gap_size: -1.0),\n])",
+            3,
+            49,
+            "`gap_size` is -1 but must be more than zero",
+        ),
+        (
+            "(pairs: [\n    (spacing: 0.0, gap: 0.0, gap_size: 400.0),\n])",
+            2,
+            23,
+            "unknown field `gap`",
+        ),
+        (
+            "(pairs: [\n    (spacing: 0.0, gap_y: 0.0),\n])",
+            2,
+            30,
+            "missing field `gap_size`",
+        ),
+        (
+            "(pairs: [\n    (spacing: 0.0, gap_y: high, gap_size: 400.0),\n])",
+            2,
+            27,
+            "Expected float",
+        ),
+        (
+            "(pairs: [\n    (\n        spacing: 0.0,\n        gap_y: 0.0,\n        \
+             gap_size: 400.0,\n        variant: Wobbling,\n    ),\n])",
+            6,
+            26,
+            "unknown variant `Wobbling`",
+        ),
+    ] {
+        let err = Course::from_ron(course.as_bytes()).unwrap_err();
+        assert!(
+            (err.line, err.column) == (line, column)
+                && err.message.starts_with(message),
+            "{} for:\n{}",
+            err,
+            course
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use bevy::prelude::*;
+
+use bevy_bird::course::{Course, CoursePair, Variant};
+use bevy_bird::editor;
+use bevy_bird::obstacle::Pipe;
+use bevy_bird::{headless_app, AppState, GameMode, Gap, Player};
+
+mod common;
+use common::{current_state, tap_key};
+
+fn pipe_count(app: &mut App) -> usize {
+    app.world
+        .query_filtered::<Entity, With<Pipe>>()
+        .iter(&app.world)
+        .count()
+}
+
+#[test]
+fn courses_are_edited_saved_and_tested_from_the_camera() {
+    let pair = |spacing, gap_y, variant| CoursePair {
+        spacing,
+        gap_y,
+        gap_size: 350.0,
+        variant,
+    };
+    // Laid out at x = 500, 800 and 1100
+    let course = Course {
+        name: "Edited".to_string(),
+        pairs: vec![
+            pair(100.0, 0.0, Variant::Still),
+            pair(300.0, 80.0, Variant::Bobbing { phase: 0.5 }),
+            pair(300.0, -60.0, Variant::Sliding { top: false }),
+        ],
+    };
+    let path = std::env::temp_dir().join(format!(
+        "bevy_bird_editor_{}.course.ron",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        ron::ser::to_string_pretty(&course, default()).unwrap(),
+    )
+    .unwrap();
+
+    let mut app = headless_app();
+    editor::edit_course(&mut app, &path).unwrap();
+    app.update();
+
+    tap_key(&mut app, KeyCode::F2);
+    assert_eq!(current_state(&app), AppState::Editor);
+    assert_eq!(pipe_count(&mut app), 2 * course.pairs.len());
+
+    // Saving rewrites what it was opened from
+    std::fs::remove_file(&path).unwrap();
+    tap_key(&mut app, KeyCode::S);
+    assert_eq!(Course::load(&path).unwrap(), course);
+    std::fs::remove_file(&path).unwrap();
+
+    // Past the first pair, the second one 200 ahead of the camera
+    let mut camera =
+        app.world.query_filtered::<&mut Transform, With<Camera2d>>();
+    camera.single_mut(&mut app.world).translation.x = 600.0;
+    tap_key(&mut app, KeyCode::T);
+    assert_eq!(current_state(&app), AppState::InGame);
+    assert_eq!(*app.world.resource::<GameMode>(), GameMode::Course);
+    assert_eq!(camera.single(&app.world).translation.x, 0.0);
+
+    let mut players = app.world.query_filtered::<Entity, With<Player>>();
+    assert_eq!(players.iter(&app.world).count(), 1);
+    let mut gaps = app.world.query::<(&Transform, &Gap)>();
+    let in_play: Vec<f32> = gaps
+        .iter(&app.world)
+        .map(|(transform, _)| transform.translation.x)
+        .collect();
+    // Two ticks in, scrolling a couple of pixels at most
+    assert_eq!(in_play.len(), 1, "{:?}", in_play);
+    assert!((in_play[0] - 200.0).abs() < 5.0, "{:?}", in_play);
+
+    // Nobody flaps, and the game over goes straight back to editing
+    while current_state(&app) != AppState::GameOver {
+        app.update();
+    }
+    tap_key(&mut app, KeyCode::Escape);
+    app.update();
+    assert_eq!(current_state(&app), AppState::Editor);
+    assert_eq!(*app.world.resource::<GameMode>(), GameMode::Endless);
+    assert_eq!(pipe_count(&mut app), 2 * course.pairs.len());
+}
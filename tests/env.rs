@@ -0,0 +1,50 @@
+use bevy_bird::env::{Action, Env, Observation, DEATH_REWARD};
+
+/// Flaps whenever the bird sinks below the middle of the next gap.
+fn follow_gap(observation: &Observation) -> Action {
+    match observation.obstacles[0] {
+        Some(next) if observation.bird_y < next.gap_y => Action::Flap,
+        _ => Action::Noop,
+    }
+}
+
+#[test]
+fn run_without_flapping_ends_in_death() {
+    let mut env = Env::new();
+    let observation = env.reset(7);
+    let first = observation.obstacles[0].expect("an obstacle ahead");
+    assert!(first.distance > 0.0);
+
+    let mut last = (observation, 0.0, false);
+    for _ in 0..600 {
+        last = env.step(Action::Noop);
+        if last.2 {
+            break;
+        }
+    }
+
+    assert!(last.2, "run never ended");
+    assert_eq!(last.1, DEATH_REWARD);
+    assert_eq!(env.step(Action::Flap).1, 0.0);
+}
+
+#[test]
+fn same_seed_and_actions_play_the_same_run() {
+    let mut env = Env::new();
+    let mut runs = Vec::new();
+    for _ in 0..2 {
+        let mut observation = env.reset(7);
+        let mut observations = vec![observation];
+        for _ in 0..900 {
+            let (next, _, done) = env.step(follow_gap(&observation));
+            observations.push(next);
+            observation = next;
+            if done {
+                break;
+            }
+        }
+        runs.push((observations, env.score()));
+    }
+
+    assert_eq!(runs[0], runs[1]);
+}
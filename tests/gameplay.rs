@@ -0,0 +1,309 @@
+use std::thread;
+use std::time::Duration;
+
+use bevy::input::mouse::MouseButtonInput;
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::{RigidBody, Velocity};
+
+use bevy_bird::config::GameConfig;
+use bevy_bird::controls::ActionButton;
+use bevy_bird::replay::TICK_SECS;
+use bevy_bird::versus::Crashed;
+use bevy_bird::{
+    headless_app, ActionKey, AppState, Background, GameMode, InPlay, Obstacle,
+    Player, Score, TickClock,
+};
+
+mod common;
+use common::{current_state, send_key, start_run};
+
+fn count<F: bevy::ecs::query::ReadOnlyWorldQuery>(app: &mut App) -> usize {
+    app.world
+        .query_filtered::<Entity, F>()
+        .iter(&app.world)
+        .count()
+}
+
+#[test]
+fn first_key_release_starts_the_run_and_picks_the_action_key() {
+    let mut app = headless_app();
+    app.update();
+    assert_eq!(current_state(&app), AppState::Menu);
+
+    send_key(&mut app, KeyCode::J, ButtonState::Pressed);
+    app.update();
+    assert_eq!(current_state(&app), AppState::Menu);
+
+    send_key(&mut app, KeyCode::J, ButtonState::Released);
+    app.update();
+    assert_eq!(current_state(&app), AppState::InGame);
+    assert_eq!(
+        app.world.resource::<ActionKey>().0,
+        ActionButton::Key(KeyCode::J)
+    );
+}
+
+#[test]
+fn mouse_click_starts_the_run_and_flaps() {
+    let mut app = headless_app();
+    app.update();
+
+    for state in [ButtonState::Pressed, ButtonState::Released] {
+        app.world.send_event(MouseButtonInput {
+            button: MouseButton::Left,
+            state,
+        });
+        app.update();
+    }
+    assert_eq!(current_state(&app), AppState::InGame);
+    assert_eq!(
+        app.world.resource::<ActionKey>().0,
+        ActionButton::Mouse(MouseButton::Left)
+    );
+
+    app.world.send_event(MouseButtonInput {
+        button: MouseButton::Left,
+        state: ButtonState::Pressed,
+    });
+    app.update();
+    let velocity = app
+        .world
+        .query_filtered::<&Velocity, With<Player>>()
+        .single(&app.world)
+        .linvel
+        .y;
+    assert!(velocity > 0.0, "bird moving at {} after a click", velocity);
+}
+
+#[test]
+fn bird_without_input_falls_and_dies_on_the_floor() {
+    let mut app = start_run(KeyCode::J);
+
+    for _ in 0..600 {
+        app.update();
+        if current_state(&app) == AppState::GameOver {
+            break;
+        }
+    }
+
+    assert_eq!(current_state(&app), AppState::GameOver);
+    let bird_y = app
+        .world
+        .query_filtered::<&Transform, With<Player>>()
+        .single(&app.world)
+        .translation
+        .y;
+    // The top of the floor collider is at -700
+    assert!(bird_y < -600.0, "bird died at y = {}", bird_y);
+}
+
+#[test]
+fn retrying_after_game_over_starts_a_fresh_run() {
+    let mut app = start_run(KeyCode::J);
+    while current_state(&app) != AppState::GameOver {
+        app.update();
+    }
+
+    send_key(&mut app, KeyCode::J, ButtonState::Pressed);
+    app.update();
+    app.update();
+
+    assert_eq!(current_state(&app), AppState::InGame);
+    let bird_y = app
+        .world
+        .query_filtered::<&Transform, With<Player>>()
+        .single(&app.world)
+        .translation
+        .y;
+    assert!(bird_y > -100.0, "bird restarted at y = {}", bird_y);
+}
+
+#[test]
+fn game_over_shows_only_the_table_the_run_was_counted_in() {
+    for (mode, table) in [
+        (GameMode::Endless, Some("High Scores\n")),
+        (GameMode::Daily, Some("Daily Challenge\n")),
+        (GameMode::Course, None),
+    ] {
+        let mut app = headless_app();
+        app.insert_resource(mode);
+        app.update();
+        send_key(&mut app, KeyCode::J, ButtonState::Pressed);
+        send_key(&mut app, KeyCode::J, ButtonState::Released);
+        while current_state(&app) != AppState::GameOver {
+            app.update();
+        }
+        app.update();
+
+        let titles: Vec<String> = app
+            .world
+            .query::<&Text>()
+            .iter(&app.world)
+            .map(|text| text.sections[0].value.clone())
+            .collect();
+        for title in ["High Scores\n", "Daily Challenge\n"] {
+            assert_eq!(
+                titles.iter().any(|shown| shown == title),
+                table == Some(title),
+                "{:?} run showed {:?}",
+                mode,
+                titles
+            );
+        }
+    }
+}
+
+#[test]
+fn passing_an_obstacle_scores_exactly_once() {
+    let mut app = start_run(KeyCode::J);
+    let sprite_size = app.world.resource::<GameConfig>().sprite_size;
+
+    app.world
+        .spawn()
+        .insert_bundle(SpatialBundle::from(Transform::from_xyz(
+            -sprite_size - 1.0,
+            0.0,
+            0.0,
+        )))
+        .insert(InPlay);
+
+    for _ in 0..5 {
+        app.update();
+    }
+
+    assert_eq!(app.world.resource::<Score>().0, 1);
+}
+
+#[test]
+fn obstacles_past_the_left_edge_are_despawned() {
+    let mut app = start_run(KeyCode::J);
+    let width = app.world.resource::<GameConfig>().width;
+    let obstacles = count::<With<Obstacle>>(&mut app);
+
+    let gone = app
+        .world
+        .spawn()
+        .insert_bundle(SpatialBundle::from(Transform::from_xyz(
+            -width, 0.0, 0.0,
+        )))
+        .insert(Obstacle)
+        .id();
+    let kept = app
+        .world
+        .spawn()
+        .insert_bundle(SpatialBundle::from(Transform::from_xyz(0.0, 0.0, 0.0)))
+        .insert(Obstacle)
+        .id();
+    app.update();
+
+    assert!(app.world.get_entity(gone).is_none());
+    assert!(app.world.get_entity(kept).is_some());
+    assert_eq!(count::<With<Obstacle>>(&mut app), obstacles + 1);
+}
+
+#[test]
+fn infinite_scroll_keeps_two_background_tiles() {
+    let mut app = headless_app();
+    app.update();
+    assert_eq!(count::<With<Background>>(&mut app), 2);
+
+    let width = app.world.resource::<GameConfig>().width;
+    for _ in 0..3 {
+        // Push the leftmost tile out of view
+        let mut backgrounds = app
+            .world
+            .query_filtered::<&mut Transform, With<Background>>();
+        let mut leftmost = backgrounds
+            .iter_mut(&mut app.world)
+            .min_by(|a, b| a.translation.x.total_cmp(&b.translation.x))
+            .unwrap();
+        leftmost.translation.x = -6.8 * width - 1.0;
+
+        app.update();
+        app.update();
+        assert_eq!(count::<With<Background>>(&mut app), 2);
+        assert!(app
+            .world
+            .query_filtered::<&Transform, With<Background>>()
+            .iter(&app.world)
+            .all(|transform| transform.translation.x > -6.8 * width));
+    }
+}
+
+#[test]
+fn ticks_keep_pace_with_the_clock() {
+    let mut app = start_run(KeyCode::J);
+    app.insert_resource(TickClock::real_time());
+    // Moves a pixel a tick
+    let marker = app
+        .world
+        .spawn()
+        .insert_bundle(SpatialBundle::default())
+        .insert(RigidBody::KinematicVelocityBased)
+        .insert(Velocity::linear(Vec2::new(1.0 / TICK_SECS, 0.0)))
+        .id();
+    let ticks_after = |app: &mut App, pause: Duration| {
+        let before = app.world.get::<Transform>(marker).unwrap().translation.x;
+        thread::sleep(pause);
+        app.update();
+        let after = app.world.get::<Transform>(marker).unwrap().translation.x;
+        (after - before).round() as u32
+    };
+
+    ticks_after(&mut app, Duration::from_millis(20));
+    // However long the update itself took on top
+    let ticks = ticks_after(&mut app, Duration::from_millis(100));
+    assert!((6..=8).contains(&ticks), "{} ticks", ticks);
+    // A stall is only caught up on so far
+    assert_eq!(ticks_after(&mut app, Duration::from_secs(1)), 8);
+}
+
+#[test]
+fn versus_run_goes_on_until_the_last_bird_crashes() {
+    let mut app = headless_app();
+    app.insert_resource(GameMode::Versus);
+    app.update();
+
+    for key_code in [KeyCode::J, KeyCode::K] {
+        send_key(&mut app, key_code, ButtonState::Pressed);
+        app.update();
+        send_key(&mut app, key_code, ButtonState::Released);
+        app.update();
+    }
+    assert_eq!(current_state(&app), AppState::InGame);
+    assert_eq!(count::<With<Player>>(&mut app), 2);
+
+    // Only player two's bird answers to their button
+    send_key(&mut app, KeyCode::K, ButtonState::Pressed);
+    app.update();
+    let mut birds: Vec<(usize, f32)> = app
+        .world
+        .query::<(&Player, &Velocity)>()
+        .iter(&app.world)
+        .map(|(player, velocity)| (player.0, velocity.linvel.y))
+        .collect();
+    birds.sort_by_key(|&(player, _)| player);
+    assert!(birds[0].1 < 0.0, "player one moving at {}", birds[0].1);
+    assert!(birds[1].1 > 0.0, "player two moving at {}", birds[1].1);
+
+    // Player one falls first, and player two keeps going
+    while count::<With<Crashed>>(&mut app) == 0 {
+        app.update();
+        assert_eq!(current_state(&app), AppState::InGame);
+    }
+    let crashed = app
+        .world
+        .query_filtered::<&Player, With<Crashed>>()
+        .single(&app.world)
+        .0;
+    assert_eq!(crashed, 0);
+
+    for _ in 0..600 {
+        app.update();
+        if current_state(&app) == AppState::GameOver {
+            return;
+        }
+    }
+    panic!("the run carried on after both birds fell");
+}
@@ -0,0 +1,155 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::Collider;
+
+use bevy_bird::autopilot::Autopilot;
+use bevy_bird::config::GameConfig;
+use bevy_bird::obstacle::{Motion, ObstacleWeights, PairMotion, Pipe};
+use bevy_bird::seed::GameSeed;
+use bevy_bird::{headless_app, AppState, Gap};
+
+mod common;
+
+/// Sprites are sized in floats, a little slack is fine.
+const EPSILON: f32 = 0.01;
+
+fn start_run() -> App {
+    let mut app = headless_app();
+    common::skip_menu(&mut app);
+    // Once to spawn the pipes, once more to draw them
+    app.update();
+    app.update();
+    app
+}
+
+/// Checks that the sprites of every pipe cover its collider, no more and no
+/// less, and returns how many pipes there were.
+fn assert_sprites_match_colliders(app: &mut App) -> usize {
+    let mut pipes = app
+        .world
+        .query_filtered::<(Entity, &Collider, &Children), With<Pipe>>();
+    let mut sprites = app.world.query::<(&Sprite, &Transform)>();
+
+    let mut checked = 0;
+    for (entity, collider, children) in pipes.iter(&app.world) {
+        let half_extents = collider.as_cuboid().unwrap().half_extents();
+        let mut bounds = Vec::new();
+        for &child in children.iter() {
+            let (sprite, transform) = sprites.get(&app.world, child).unwrap();
+            let size = sprite.custom_size.unwrap();
+            let y = transform.translation.y;
+            bounds.push((y - size.y / 2.0, y + size.y / 2.0, size.x));
+        }
+        bounds.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // End to end from one side of the collider to the other
+        let mut reached = -half_extents.y;
+        for &(bottom, top, width) in &bounds {
+            assert!(
+                (bottom - reached).abs() < EPSILON,
+                "pipe {:?} has a gap or overlap at {}: {:?}",
+                entity,
+                reached,
+                bounds
+            );
+            // Nothing narrower than what the bird crashes into
+            assert!(
+                width >= half_extents.x * 2.0 - EPSILON,
+                "pipe {:?} is drawn {} wide over a {} wide collider",
+                entity,
+                width,
+                half_extents.x * 2.0
+            );
+            reached = top;
+        }
+        assert!(
+            (reached - half_extents.y).abs() < EPSILON,
+            "pipe {:?} is drawn up to {} but its collider reaches {}",
+            entity,
+            reached,
+            half_extents.y
+        );
+        checked += 1;
+    }
+    checked
+}
+
+#[test]
+fn pipe_sprites_match_their_colliders_at_any_size() {
+    let mut app = start_run();
+    assert_eq!(assert_sprites_match_colliders(&mut app), 2);
+
+    let mut pipes = app.world.query_filtered::<Entity, With<Pipe>>();
+    let pipes: Vec<Entity> = pipes.iter(&app.world).collect();
+    let drawn = app.world.get::<Children>(pipes[0]).unwrap().to_vec();
+    app.update();
+    assert_eq!(
+        app.world.get::<Children>(pipes[0]).unwrap().to_vec(),
+        drawn,
+        "pipes are only redrawn when their collider changes"
+    );
+
+    // Shorter than the cap, and long enough to end on part of a segment
+    for (pipe, half_extents) in pipes.iter().zip([(20.0, 45.0), (80.0, 1010.0)])
+    {
+        app.world
+            .entity_mut(*pipe)
+            .insert(Collider::cuboid(half_extents.0, half_extents.1));
+    }
+    app.update();
+    assert_eq!(assert_sprites_match_colliders(&mut app), 2);
+}
+
+#[test]
+fn moving_pipes_stay_where_their_gap_is() {
+    let mut app = headless_app();
+    let moving = ObstacleWeights {
+        still: 0.0,
+        bobbing: 1.0,
+        breathing: 1.0,
+        sliding: 1.0,
+    };
+    app.insert_resource(GameConfig {
+        obstacle_weights: moving,
+        hardest_obstacle_weights: moving,
+        ..GameConfig::default()
+    })
+    .insert_resource(GameSeed::fixed(7))
+    .insert_resource(Autopilot);
+    common::skip_menu(&mut app);
+
+    let mut pairs = app.world.query::<(&Transform, &Gap, &PairMotion)>();
+    let mut pipes = app.world.query::<(&Transform, &Collider)>();
+    let (mut bobbing, mut breathing, mut sliding) = (false, false, false);
+    // Twenty seconds of play
+    for _ in 0..1200 {
+        app.update();
+        for (top, gap, pair) in pairs.iter(&app.world) {
+            let (bottom, collider) =
+                pipes.get(&app.world, pair.bottom).unwrap();
+            let half_height = collider.as_cuboid().unwrap().half_extents().y;
+            let top_edge = top.translation.y - half_height;
+            let bottom_edge = bottom.translation.y + half_height;
+            assert!(
+                (top_edge - (gap.y + gap.size / 2.0)).abs() < EPSILON
+                    && (bottom_edge - (gap.y - gap.size / 2.0)).abs() < EPSILON,
+                "{:?} pipes span {}..{} but the gap is {:?}",
+                pair.motion,
+                bottom_edge,
+                top_edge,
+                gap
+            );
+            match pair.motion {
+                Motion::Still => {}
+                Motion::Bobbing { .. } => bobbing = true,
+                Motion::Breathing { .. } => breathing = true,
+                Motion::Sliding { .. } => sliding = true,
+            }
+        }
+    }
+    assert!(bobbing && breathing && sliding);
+    assert_eq!(
+        app.world.resource::<State<AppState>>().current(),
+        &AppState::InGame,
+        "the autopilot can't get past moving pipes"
+    );
+}
@@ -0,0 +1,83 @@
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+
+use bevy_bird::online::{self, OpponentBird, Relay, TcpRelay};
+use bevy_bird::seed::GameSeed;
+use bevy_bird::{headless_app, AppState, Player};
+
+fn online_client(addr: SocketAddr) -> App {
+    let mut app = headless_app();
+    online::connect(&mut app, TcpRelay::connect(addr).unwrap(), "test");
+    app
+}
+
+fn press(app: &mut App, key_code: KeyCode, state: ButtonState) {
+    app.world.send_event(KeyboardInput {
+        scan_code: 0,
+        key_code: Some(key_code),
+        state,
+    });
+    app.update();
+}
+
+fn bird_y<F: bevy::ecs::query::ReadOnlyWorldQuery>(app: &mut App) -> f32 {
+    app.world
+        .query_filtered::<&Transform, F>()
+        .single(&app.world)
+        .translation
+        .y
+}
+
+#[test]
+fn clients_race_the_same_course_and_see_each_other() {
+    let addr = relay::spawn_local().unwrap();
+    let mut first = online_client(addr);
+    let mut second = online_client(addr);
+
+    let started = Instant::now();
+    while !(first.world.resource::<Relay>().matched()
+        && second.world.resource::<Relay>().matched())
+    {
+        assert!(started.elapsed() < Duration::from_secs(5), "never paired");
+        first.update();
+        second.update();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+        first.world.resource::<GameSeed>().seed(),
+        second.world.resource::<GameSeed>().seed()
+    );
+
+    for app in [&mut first, &mut second] {
+        press(app, KeyCode::J, ButtonState::Pressed);
+        press(app, KeyCode::J, ButtonState::Released);
+        assert_eq!(
+            *app.world.resource::<State<AppState>>().current(),
+            AppState::InGame
+        );
+    }
+
+    // Only the first player flaps
+    for tick in 0..30 {
+        match tick {
+            5 => press(&mut first, KeyCode::J, ButtonState::Pressed),
+            6 => press(&mut first, KeyCode::J, ButtonState::Released),
+            _ => first.update(),
+        }
+        second.update();
+    }
+    thread::sleep(Duration::from_millis(200));
+    second.update();
+
+    let opponent_y = bird_y::<With<OpponentBird>>(&mut second);
+    assert_eq!(opponent_y, bird_y::<With<Player>>(&mut first));
+    assert!(
+        opponent_y > bird_y::<With<Player>>(&mut second),
+        "the opponent flapped, so should be higher"
+    );
+}